@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use num_bigint::BigInt;
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey, pkcs1v15::{Signature, VerifyingKey}, pkcs8::{DecodePublicKey, EncodePublicKey}, signature::Verifier};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::packets::{HandleError, login::Property};
+
+/// A player as the session server knows them.
+#[derive(Deserialize, Debug, Clone)]
+pub struct GameProfile {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+}
+
+impl GameProfile {
+    pub fn uuid(&self) -> Result<Uuid, HandleError> {
+        Uuid::parse_str(&self.id)
+            .map_err(|_| HandleError::BadPacket(format!("session server sent an invalid UUID {:?}", self.id)))
+    }
+}
+
+/// What's needed to log players in in online mode: the key pair clients
+/// encrypt their shared secret with, and a way to ask the session server
+/// whether they really are who they say.
+pub struct Authenticator {
+    key: RsaPrivateKey,
+    public_key: Vec<u8>,
+    session_server: String,
+    http: reqwest::Client,
+}
+
+impl Authenticator {
+    /// Generates a fresh key pair, like vanilla does on every start.
+    pub fn new(session_server: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)?;
+        let public_key = RsaPublicKey::from(&key).to_public_key_der()?.into_vec();
+
+        Ok(Authenticator {
+            key,
+            public_key,
+            session_server: session_server.trim_end_matches('/').to_string(),
+            http: reqwest::Client::builder().timeout(Duration::from_secs(10)).build()?,
+        })
+    }
+
+    /// The public key in DER form, as sent in the Encryption Request.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, HandleError> {
+        self.key.decrypt(Pkcs1v15Encrypt, data)
+            .map_err(|_| HandleError::BadPacket("failed to decrypt encryption response".to_string()))
+    }
+
+    /// Asks the session server whether `username` told it they're joining
+    /// the server identified by `server_hash`, returning their profile if so.
+    pub async fn has_joined(&self, username: &str, server_hash: &str) -> Result<Option<GameProfile>, HandleError> {
+        let response = self.http.get(format!("{}/session/minecraft/hasJoined", self.session_server))
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send().await
+            .and_then(|response| response.error_for_status());
+
+        let result = match response {
+            Ok(response) if response.status() == reqwest::StatusCode::NO_CONTENT => return Ok(None),
+            Ok(response) => response.json().await.map(Some),
+            Err(e) => Err(e),
+        };
+
+        result.map_err(|e| {
+            eprintln!("failed to reach session server; err = {}", e);
+            HandleError::disconnect("Authentication servers are down. Please try again later, sorry!")
+        })
+    }
+}
+
+/// Checks the signature clients with a profile key send in place of the
+/// verify token: SHA256withRSA over the verify token followed by the salt.
+pub fn verify_signature(public_key: &[u8], verify_token: &[u8], salt: i64, signature: &[u8]) -> bool {
+    let Ok(key) = RsaPublicKey::from_public_key_der(public_key) else {
+        return false;
+    };
+    let Ok(signature) = Signature::try_from(signature) else {
+        return false;
+    };
+
+    let mut message = verify_token.to_vec();
+    message.extend(salt.to_be_bytes());
+
+    VerifyingKey::<Sha256>::new(key).verify(&message, &signature).is_ok()
+}
+
+/// The "server ID" clients and the session server agree on: a SHA-1 of
+/// everything exchanged, printed as a signed (two's complement) hex number.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    let digest = Sha1::new()
+        .chain_update(server_id)
+        .chain_update(shared_secret)
+        .chain_update(public_key)
+        .finalize();
+
+    BigInt::from_signed_bytes_be(&digest).to_str_radix(16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::server_hash;
+
+    #[test]
+    fn server_hashes() {
+        assert_eq!(server_hash("Notch", &[], &[]), "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48");
+        assert_eq!(server_hash("jeb_", &[], &[]), "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1");
+        assert_eq!(server_hash("simon", &[], &[]), "88e16a1019277b15d58faf0541e11910eb756f6");
+    }
+}
@@ -0,0 +1,320 @@
+//! Load tests hubby: ramps up a crowd of offline mode bots that walk around
+//! and chat, then reports how it went.
+//!
+//! Without an ADDRESS, the bots join a hub run in-process, on the default
+//! config or the one given with `--config`, and the report includes what
+//! the server itself handled. Its rate limits are lifted, since the bots
+//! all come from one address. A hub running elsewhere applies its limits
+//! to the bots like anyone else, so raise `[limits]` there (or the bots
+//! will mostly get throttled) before pointing this at it.
+
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, process, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use hubby::{Config, Server, client::{Client, ClientError}, keep_alive::KEEP_ALIVE_TIMEOUT, packets::play::{ChatMessage, ConfirmTeleportation, SetPlayerPosition, SyncPlayerPosition}};
+use serde_mcje::types::PrefixedArray;
+use tokio::{net::TcpStream, task::JoinSet, time::{self, Instant, MissedTickBehavior}};
+use tokio_util::sync::CancellationToken;
+
+const USAGE: &str = "usage: hubby-bench [ADDRESS | --config PATH] [--bots N] [--ramp-up SECONDS] [--duration SECONDS] [--move-rate PER_SECOND] [--chat-rate PER_SECOND]";
+
+struct Options {
+    /// Where a hub is already running, if the bots aren't to join one of their own.
+    address: Option<String>,
+    /// The config for the hub run in-process.
+    config: Option<PathBuf>,
+    bots: usize,
+    /// How long it takes for all the bots to have started connecting.
+    ramp_up: Duration,
+    /// How long to keep going once they all have.
+    duration: Duration,
+    /// Position updates per second, per bot.
+    move_rate: f64,
+    /// Chat messages per second, per bot.
+    chat_rate: f64,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            address: None,
+            config: None,
+            bots: 100,
+            ramp_up: Duration::from_secs(10),
+            duration: Duration::from_secs(30),
+            move_rate: 4.0,
+            chat_rate: 0.1,
+        };
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.address = Some(arg);
+                continue;
+            }
+
+            let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+            let number = || value.parse::<f64>().ok().filter(|x| x.is_finite() && *x >= 0.0)
+                .ok_or_else(|| format!("invalid value for {}: {}", arg, value));
+
+            match arg.as_str() {
+                "--config" => options.config = Some(PathBuf::from(value)),
+                "--bots" => options.bots = value.parse().map_err(|_| format!("invalid number of bots: {}", value))?,
+                "--ramp-up" => options.ramp_up = Duration::from_secs_f64(number()?),
+                "--duration" => options.duration = Duration::from_secs_f64(number()?),
+                "--move-rate" => options.move_rate = number()?,
+                "--chat-rate" => options.chat_rate = number()?,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        if options.address.is_some() && options.config.is_some() {
+            return Err("--config is only for the hub run in-process, not one at ADDRESS".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+/// Packets sent and received by all the bots, counted as they go.
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+struct BotReport {
+    /// How long it took to get from connecting to being in the world, or why it didn't.
+    joined: Result<Duration, String>,
+    /// Why the bot was dropped after it joined, if it was.
+    dropped: Option<String>,
+}
+
+/// An interval ticking `rate` times a second, or never if it's 0.
+fn every(rate: f64) -> Option<time::Interval> {
+    (rate > 0.0).then(|| {
+        let period = Duration::from_secs_f64(1.0 / rate);
+        // Start out of step with the other bots, so they don't all send at once.
+        let mut interval = time::interval_at(Instant::now() + period.mul_f64(rand::random()), period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    })
+}
+
+async fn tick(interval: &mut Option<time::Interval>) {
+    match interval {
+        Some(interval) => { interval.tick().await; },
+        None => std::future::pending().await,
+    }
+}
+
+async fn join(address: &str, name: &str) -> Result<(Client, SyncPlayerPosition), ClientError> {
+    let mut client = Client::connect(address).await?;
+    client.login_offline(name).await?;
+    let position = client.wait_for::<SyncPlayerPosition>().await?;
+    client.send(&ConfirmTeleportation { teleport_id: position.teleport_id.0.into() }).await?;
+
+    Ok((client, position))
+}
+
+async fn run_bot(id: usize, address: Arc<str>, options: Arc<Options>, counters: Arc<Counters>, stop: CancellationToken) -> BotReport {
+    let name = format!("bot{}", id);
+    let start = Instant::now();
+
+    let (mut client, position) = match join(&address, &name).await {
+        Ok(x) => x,
+        Err(e) => return BotReport { joined: Err(e.to_string()), dropped: None },
+    };
+    let joined = start.elapsed();
+    // Bots that neither move nor chat may hear nothing but keep-alives,
+    // which are further apart than the client's usual timeout.
+    client.set_timeout(KEEP_ALIVE_TIMEOUT);
+
+    let (mut x, mut z) = (position.x, position.z);
+    let mut moves = every(options.move_rate);
+    let mut chats = every(options.chat_rate);
+    let mut chat_count = 0;
+
+    let dropped = loop {
+        let sent = tokio::select! {
+            _ = stop.cancelled() => break None,
+            packet = client.recv() => match packet {
+                Ok(_) => {
+                    counters.received.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                },
+                Err(e) => break Some(e.to_string()),
+            },
+            _ = tick(&mut moves) => {
+                // Wander around spawn, a step at a time.
+                x += rand::random::<f64>() - 0.5;
+                z += rand::random::<f64>() - 0.5;
+                client.send(&SetPlayerPosition { x, y: position.y, z, on_ground: true }).await
+            },
+            _ = tick(&mut chats) => {
+                chat_count += 1;
+                client.send(&ChatMessage {
+                    message: format!("hello from {} (#{})", name, chat_count),
+                    timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64,
+                    salt: rand::random(),
+                    signature: PrefixedArray(vec![]),
+                    signed_preview: false,
+                }).await
+            },
+        };
+
+        match sent {
+            Ok(()) => counters.sent.fetch_add(1, Ordering::Relaxed),
+            Err(e) => break Some(e.to_string()),
+        };
+    };
+
+    if let Err(e) = client.close().await {
+        eprintln!("{} failed to hang up cleanly; err = {}", name, e);
+    }
+
+    BotReport { joined: Ok(joined), dropped }
+}
+
+/// Starts a hub in this process for the bots to join, on a free local port.
+async fn start_server(options: &Options) -> Result<(Arc<Server>, SocketAddr), String> {
+    let mut config = match &options.config {
+        Some(path) => Config::read(path).map_err(|e| e.to_string())?,
+        None => Config::default(),
+    };
+    // A refill of 0 turns rate limiting off.
+    config.limits.connections.refill = Duration::ZERO;
+    config.limits.logins.refill = Duration::ZERO;
+    config.limits.max_connections = config.limits.max_connections.max(options.bots);
+    // The report would be lost among the bots' chatter.
+    config.chat.log = false;
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0").and_then(|x| x.local_addr()).map_err(|e| e.to_string())?;
+    let server = Server::builder().config(config).bind(address).build().map_err(|e| e.to_string())?;
+    tokio::spawn({
+        let server = server.clone();
+        async move {
+            if let Err(e) = server.run().await {
+                eprintln!("server failed; err = {}", e);
+                process::exit(1);
+            }
+        }
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while TcpStream::connect(address).await.is_err() {
+        if Instant::now() > deadline {
+            return Err(format!("server didn't start listening on {}", address));
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+
+    Ok((server, address))
+}
+
+/// The value `percent`% of the way through `sorted`.
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    let index = ((sorted.len() - 1) as f64 * percent / 100.0).round() as usize;
+    sorted[index]
+}
+
+fn print_errors(what: &str, errors: &[String]) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for e in errors {
+        *counts.entry(e).or_default() += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    for (e, count) in counts {
+        println!("  {} {}x: {}", what, count, e);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(x) => Arc::new(x),
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(1);
+        }
+    };
+
+    let (server, address) = match &options.address {
+        Some(address) => (None, address.clone()),
+        None => match start_server(&options).await {
+            Ok((server, address)) => (Some(server), address.to_string()),
+            Err(e) => {
+                eprintln!("failed to start a server; err = {}", e);
+                process::exit(1);
+            }
+        },
+    };
+    let address: Arc<str> = address.into();
+
+    println!("starting {} bots against {} over {:?}", options.bots, address, options.ramp_up);
+
+    let counters = Arc::new(Counters::default());
+    let stop = CancellationToken::new();
+    let mut bots = JoinSet::new();
+
+    let start = Instant::now();
+    let spacing = options.ramp_up.checked_div(options.bots as u32).unwrap_or_default();
+    for id in 0..options.bots {
+        time::sleep_until(start + spacing * id as u32).await;
+        bots.spawn(run_bot(id, address.clone(), options.clone(), counters.clone(), stop.clone()));
+    }
+
+    // Only count traffic from once everyone's had the chance to join.
+    let ramped_up = Instant::now();
+    let (sent, received) = (counters.sent.load(Ordering::Relaxed), counters.received.load(Ordering::Relaxed));
+    let server_traffic = |server: &Server| (server.traffic.received.load(Ordering::Relaxed), server.traffic.sent.load(Ordering::Relaxed));
+    let before = server.as_deref().map(server_traffic);
+    time::sleep(options.duration).await;
+    let elapsed = ramped_up.elapsed().as_secs_f64();
+    let sent = counters.sent.load(Ordering::Relaxed) - sent;
+    let received = counters.received.load(Ordering::Relaxed) - received;
+    let handled = server.as_deref().zip(before).map(|(server, (received, sent))| {
+        let (now_received, now_sent) = server_traffic(server);
+        (now_received - received, now_sent - sent, server.players.len())
+    });
+    stop.cancel();
+
+    let mut latencies = Vec::new();
+    let mut failures = Vec::new();
+    let mut drops = Vec::new();
+    while let Some(report) = bots.join_next().await {
+        let report = match report {
+            Ok(x) => x,
+            Err(e) => BotReport { joined: Err(format!("bot panicked: {}", e)), dropped: None },
+        };
+
+        match report.joined {
+            Ok(latency) => latencies.push(latency),
+            Err(e) => failures.push(e),
+        }
+        drops.extend(report.dropped);
+    }
+
+    println!("joined: {}/{} ({} failed, {} dropped afterwards)", latencies.len(), options.bots, failures.len(), drops.len());
+    print_errors("failed", &failures);
+    print_errors("dropped", &drops);
+
+    if !latencies.is_empty() {
+        latencies.sort();
+        println!(
+            "join latency: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            percentile(&latencies, 50.0), percentile(&latencies, 90.0), percentile(&latencies, 99.0), latencies[latencies.len() - 1],
+        );
+    }
+
+    println!(
+        "over {:.1}s: {} packets sent ({:.0}/s), {} received from the server ({:.0}/s)",
+        elapsed, sent, sent as f64 / elapsed, received, received as f64 / elapsed,
+    );
+    if let Some((received, sent, online)) = handled {
+        println!(
+            "server over {:.1}s: {} packets handled ({:.0}/s), {} sent ({:.0}/s), {} players online at the end",
+            elapsed, received, received as f64 / elapsed, sent, sent as f64 / elapsed, online,
+        );
+    }
+}
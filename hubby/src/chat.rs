@@ -0,0 +1,122 @@
+use serde_mcje::types::VarInt;
+
+use crate::{packets::{HandleError, play::SystemChat, text_component}, player::Player, server::Server};
+
+/// The chat types in the registry codec, by ID. Player chat is sent as
+/// [`SYSTEM`] too, preformatted, since [`CHAT`] messages are expected to be
+/// signed by whoever sent them.
+pub const CHAT: i32 = 0;
+pub const SYSTEM: i32 = 1;
+/// Shown above the hotbar, as the action bar.
+pub const GAME_INFO: i32 = 2;
+
+/// The longest message clients let players type.
+pub const MAX_MESSAGE_LENGTH: usize = 256;
+
+/// Checks a message or command from a client the way vanilla servers do,
+/// which kick anyone who sends what their client wouldn't.
+pub fn validate(message: &str) -> Result<(), HandleError> {
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(HandleError::ProtocolViolation(format!("chat message longer than {} characters", MAX_MESSAGE_LENGTH)));
+    }
+    if message.chars().any(|c| c == '§' || c.is_control()) {
+        return Err(HandleError::disconnect("Illegal characters in chat"));
+    }
+
+    Ok(())
+}
+
+/// Sends `component` (JSON text) to `player` as chat of type `chat_type`.
+pub fn send_component(player: &Player, component: String, chat_type: i32) -> Result<(), HandleError> {
+    player.handle.send_packet(SystemChat { content: component, chat_type: VarInt(chat_type) })
+}
+
+/// Sends `player` a message in chat.
+pub fn send(player: &Player, message: &str) -> Result<(), HandleError> {
+    send_component(player, text_component(message), SYSTEM)
+}
+
+/// Shows `player` a message above their hotbar.
+pub fn action_bar(player: &Player, message: &str) -> Result<(), HandleError> {
+    send_component(player, text_component(message), GAME_INFO)
+}
+
+/// Sends everyone online a message in chat.
+pub fn broadcast(server: &Server, message: &str) {
+    for player in server.players.all() {
+        // Whoever's connection is closed is about to leave anyway.
+        let _ = send(&player, message);
+    }
+}
+
+/// Shows everyone online a message above their hotbar.
+pub fn broadcast_action_bar(server: &Server, message: &str) {
+    for player in server.players.all() {
+        let _ = action_bar(&player, message);
+    }
+}
+
+/// Passes on what `sender` said to everyone on the hub who has chat shown,
+/// in the configured format.
+pub fn player_message(server: &Server, sender: &Player, message: &str) {
+    let config = server.config();
+    if config.chat.log {
+        println!("<{}> {}", sender.name, message);
+    }
+
+    // The message goes in last, so whatever placeholders it has are left as they are.
+    let content = text_component(&config.chat.format.replace("{player}", &sender.name).replace("{message}", message));
+    for player in server.players.all() {
+        // Players can choose to see only system messages, or nothing at all.
+        let hidden = player.settings().is_some_and(|x| x.chat_mode.0 != 0);
+        if player.proxy.is_none() && !hidden {
+            let _ = send_component(&player, content.clone(), SYSTEM);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{client::Client, config::Config, packets::play::{ChatCommand, ChatMessage, PlayerInfo, SystemChat}, server::Server};
+
+    use super::{GAME_INFO, SYSTEM};
+
+    fn chat_message(message: &str) -> ChatMessage {
+        ChatMessage { message: message.to_string(), timestamp: 0, salt: 0, signature: Default::default(), signed_preview: false }
+    }
+
+    #[tokio::test]
+    async fn chat_and_commands() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        config.chat.format = "{player}: {message}".to_string();
+        let server = Server::builder().config(config.clone()).build().unwrap();
+
+        let mut alex = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        alex.login_offline("Alex").await.unwrap();
+        alex.wait_for::<PlayerInfo>().await.unwrap();
+        let mut steve = Client::in_memory(&server, "127.0.0.1:50001".parse().unwrap());
+        steve.login_offline("Steve").await.unwrap();
+        steve.wait_for::<PlayerInfo>().await.unwrap();
+
+        alex.send(&chat_message("hi {player}")).await.unwrap();
+        let chat = steve.wait_for::<SystemChat>().await.unwrap();
+        assert_eq!((chat.content.as_str(), chat.chat_type.0), (r#"{"text":"Alex: hi {player}"}"#, SYSTEM));
+        assert_eq!(alex.wait_for::<SystemChat>().await.unwrap().content, chat.content);
+
+        steve.send(&ChatCommand { command: "spawn".to_string(), timestamp: 0, salt: 0, argument_signatures: Default::default(), signed_preview: false }).await.unwrap();
+        assert!(steve.wait_for::<SystemChat>().await.unwrap().content.contains(&config.messages.unknown_command));
+
+        super::broadcast_action_bar(&server, "Welcome");
+        let action_bar = alex.wait_for::<SystemChat>().await.unwrap();
+        assert_eq!((action_bar.content.as_str(), action_bar.chat_type.0), (r#"{"text":"Welcome"}"#, GAME_INFO));
+
+        steve.send(&chat_message("§cred")).await.unwrap();
+        let reason = loop {
+            if let Err(e) = steve.recv().await {
+                break e.to_string();
+            }
+        };
+        assert!(reason.contains("Illegal characters"), "{}", reason);
+    }
+}
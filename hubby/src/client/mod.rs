@@ -0,0 +1,642 @@
+//! The other side of the protocol: a headless client for testing hubby (or
+//! anything else speaking protocol 759) end to end.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), hubby::client::ClientError> {
+//! use hubby::{client::Client, packets::play::SyncPlayerPosition};
+//!
+//! let mut client = Client::connect("127.0.0.1:25565").await?;
+//! let profile = client.login_offline("Steve").await?;
+//! assert_eq!(profile.username, "Steve");
+//!
+//! let position = client.wait_for::<SyncPlayerPosition>().await?;
+//! assert_eq!(position.y, 64.0);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod session;
+
+use std::{fmt::{self, Display}, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use rsa::{Pkcs1v15Encrypt, RsaPublicKey, pkcs8::DecodePublicKey};
+use serde::{Deserialize, Serialize};
+use serde_mcje::{from_slice, to_vec, types::{PrefixedArray, RemainingBytes, VarInt}};
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, net::TcpStream, time::{self, Instant}};
+
+use crate::{auth, codec::{self, CipherReader, Encryptor}, connection::{Connection, ConnectionState, MAX_PACKET_LENGTH}, forwarding::{VelocityPlayer, VELOCITY_CHANNEL}, packets::{HandleError, IdentifiedPacket, PROTOCOL_VERSION, handshaking::Handshake, login::{EncryptionRequest, EncryptionResponse, LoginDisconnect, LoginPluginRequest, LoginPluginResponse, LoginStart, LoginSuccess, SetCompression, Verification}, play::{ClientboundKeepAlive, Disconnect, ServerboundKeepAlive}, status::{PingRequest, PingResponse, SamplePlayer, StatusRequest, StatusResponse}}, server::Server, varint::*};
+
+use self::session::Account;
+
+/// How long [`Client::recv`] waits for a packet unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ClientError {
+    SerdeMCJE(serde_mcje::Error),
+    Io(io::Error),
+    BadPacket(String),
+    /// A packet came in other than the one that was expected next.
+    UnexpectedPacket { state: ConnectionState, expected: i32, got: i32 },
+    /// The server kicked us. Holds the reason as a JSON text component.
+    Disconnected(String),
+    Timeout,
+    /// The session server wouldn't let us join.
+    Auth(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::SerdeMCJE(e) => write!(formatter, "failed to (de)serialize packet: {}", e),
+            ClientError::Io(e) => write!(formatter, "i/o error: {}", e),
+            ClientError::BadPacket(reason) => write!(formatter, "bad packet: {}", reason),
+            ClientError::UnexpectedPacket { state, expected, got } => {
+                write!(formatter, "expected packet 0x{:02X} in {:?}, got 0x{:02X}", expected, state, got)
+            },
+            ClientError::Disconnected(reason) => write!(formatter, "disconnected: {}", reason),
+            ClientError::Timeout => write!(formatter, "timed out waiting for a packet"),
+            ClientError::Auth(reason) => write!(formatter, "failed to authenticate: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::SerdeMCJE(e) => Some(e),
+            ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<serde_mcje::Error> for ClientError {
+    fn from(e: serde_mcje::Error) -> Self {
+        ClientError::SerdeMCJE(e)
+    }
+}
+
+impl From<VarIntError> for ClientError {
+    fn from(e: VarIntError) -> Self {
+        match e {
+            VarIntError::Io(e) => ClientError::Io(e),
+            e => ClientError::BadPacket(format!("invalid VarInt; err = {}", e)),
+        }
+    }
+}
+
+impl From<HandleError> for ClientError {
+    fn from(e: HandleError) -> Self {
+        ClientError::BadPacket(e.to_string())
+    }
+}
+
+/// A packet as it came in, to be parsed once it's known what it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub id: i32,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// Parses this as a `T`, failing if it isn't one.
+    pub fn parse<'a, T: Deserialize<'a> + IdentifiedPacket>(&'a self, state: ConnectionState) -> Result<T, ClientError> {
+        if self.id != T::ID {
+            return Err(ClientError::UnexpectedPacket { state, expected: T::ID, got: self.id });
+        }
+
+        Ok(from_slice(&self.data)?)
+    }
+}
+
+/// What a server shows about itself in the server list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub online: u32,
+    pub max: u32,
+    /// A JSON text component, or a plain string.
+    pub motd: serde_json::Value,
+    /// Some of the players that are online, if the server shares them.
+    pub sample: Vec<SamplePlayer>,
+    /// The round trip time of a Ping Request.
+    pub latency: Duration,
+}
+
+impl ServerStatus {
+    fn parse(status: &serde_json::Value, latency: Duration) -> Result<ServerStatus, ClientError> {
+        let players = &status["players"];
+        let count = |key: &str| players[key].as_u64()
+            .map(|x| x.min(u32::MAX as u64) as u32)
+            .ok_or_else(|| ClientError::BadPacket(format!("status is missing players.{}", key)));
+
+        Ok(ServerStatus {
+            online: count("online")?,
+            max: count("max")?,
+            motd: status["description"].clone(),
+            // Leave out anything malformed instead of the whole status.
+            sample: players["sample"].as_array().into_iter().flatten()
+                .filter_map(|x| serde_json::from_value(x.clone()).ok())
+                .collect(),
+            latency,
+        })
+    }
+}
+
+type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// A connection to a server, from the client's end. Everything that's
+/// received goes through [`Client::recv`], which gives up after
+/// [`Client::set_timeout`] rather than hanging a test forever.
+///
+/// Sending and receiving are both cancel safe, so they can be raced against
+/// each other in a `select!`: a partly read packet is kept until the rest
+/// comes in, and a partly written one is finished by the next call.
+pub struct Client {
+    reader: CipherReader<ReadHalf>,
+    /// What's been read but not yet made into packets.
+    received: Vec<u8>,
+    writer: WriteHalf,
+    /// Frames that are ready to go but haven't been written out yet.
+    unsent: Vec<u8>,
+    cipher: Option<Encryptor>,
+    compression: Option<usize>,
+    state: ConnectionState,
+    /// The address and port the handshake claims we connected to.
+    server_address: String,
+    server_port: u16,
+    timeout: Duration,
+    /// Who to say we are, and the secret to sign it with, if the server
+    /// asks like it would Velocity.
+    velocity: Option<(Vec<u8>, VelocityPlayer)>,
+}
+
+impl Client {
+    /// Connects to `address` (`host:port`), giving the host as it's written
+    /// there in the handshake, like vanilla clients do.
+    pub async fn connect(address: &str) -> io::Result<Client> {
+        let stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        let peer = stream.peer_addr()?;
+
+        let host = match address.rsplit_once(':') {
+            Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']').to_string(),
+            None => peer.ip().to_string(),
+        };
+
+        Ok(Client::new(stream, &host, peer.port()))
+    }
+
+    /// Asks the server at `address` (`host:port`) for its status and pings
+    /// it, the same as the server list does.
+    pub async fn query(address: &str) -> Result<ServerStatus, ClientError> {
+        let mut client = Client::connect(address).await?;
+
+        let status = client.status().await?;
+        let latency = client.ping().await?;
+
+        ServerStatus::parse(&status, latency)
+    }
+
+    /// Talks to a server over `stream`, saying it's at `server_address` and
+    /// `server_port` in the handshake.
+    pub fn new<S>(stream: S, server_address: &str, server_port: u16) -> Client
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let reader: ReadHalf = Box::new(reader);
+
+        Client {
+            reader: CipherReader::new(reader),
+            received: Vec::new(),
+            writer: Box::new(writer),
+            unsent: Vec::new(),
+            cipher: None,
+            compression: None,
+            state: ConnectionState::Handshaking,
+            server_address: server_address.to_string(),
+            server_port,
+            timeout: DEFAULT_TIMEOUT,
+            velocity: None,
+        }
+    }
+
+    /// Connects straight to `server` through an in-memory pipe, as if from
+    /// `peer`, without either side touching the network.
+    pub fn in_memory(server: &Arc<Server>, peer: SocketAddr) -> Client {
+        let (client, socket) = tokio::io::duplex(64 * 1024);
+        server.tasks.spawn(Connection::new(socket, peer, server.clone()).listen());
+
+        Client::new(client, "localhost", 25565)
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Changes what the handshake says we connected to.
+    pub fn set_server_address(&mut self, server_address: String) {
+        self.server_address = server_address;
+    }
+
+    /// Answers the server's request for who's logging in like a Velocity
+    /// proxy would, signing `player` with `secret`.
+    pub fn forward_velocity(&mut self, secret: &[u8], player: VelocityPlayer) {
+        self.velocity = Some((secret.to_vec(), player));
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sends the handshake, moving on to `next`, which is either Status or Login.
+    pub async fn handshake(&mut self, next: ConnectionState) -> Result<(), ClientError> {
+        let next_state = match next {
+            ConnectionState::Status => 1,
+            ConnectionState::Login => 2,
+            _ => return Err(ClientError::BadPacket(format!("can't hand shake into {:?}", next))),
+        };
+
+        self.send(&Handshake {
+            protocol_version: VarInt(PROTOCOL_VERSION),
+            server_address: self.server_address.clone(),
+            server_port: self.server_port,
+            next_state: VarInt(next_state),
+        }).await?;
+        self.state = next;
+
+        Ok(())
+    }
+
+    /// Asks for the server's status, handshaking first if that hasn't happened yet.
+    pub async fn status(&mut self) -> Result<serde_json::Value, ClientError> {
+        if self.state == ConnectionState::Handshaking {
+            self.handshake(ConnectionState::Status).await?;
+        }
+
+        self.send(&StatusRequest {}).await?;
+        let response = self.expect::<StatusResponse>().await?;
+
+        serde_json::from_str(&response.status)
+            .map_err(|e| ClientError::BadPacket(format!("invalid status JSON; err = {}", e)))
+    }
+
+    /// Pings the server, returning how long the round trip took. Only works
+    /// in the Status state, usually after [`Client::status`].
+    pub async fn ping(&mut self) -> Result<Duration, ClientError> {
+        let payload = rand::random();
+        let start = Instant::now();
+
+        self.send(&PingRequest { payload }).await?;
+        let response = self.expect::<PingResponse>().await?;
+        if response.payload != payload {
+            return Err(ClientError::BadPacket(format!("ping answered with {} instead of {}", response.payload, payload)));
+        }
+
+        Ok(start.elapsed())
+    }
+
+    /// Logs in as `name` to a server in offline mode.
+    pub async fn login_offline(&mut self, name: &str) -> Result<LoginSuccess, ClientError> {
+        self.login(name, None).await
+    }
+
+    /// Logs in as `account`, joining through its session server if the
+    /// server is in online mode.
+    pub async fn login_online(&mut self, account: &Account) -> Result<LoginSuccess, ClientError> {
+        self.login(&account.name, Some(account)).await
+    }
+
+    async fn login(&mut self, name: &str, account: Option<&Account>) -> Result<LoginSuccess, ClientError> {
+        if self.state == ConnectionState::Handshaking {
+            self.handshake(ConnectionState::Login).await?;
+        }
+
+        self.send(&LoginStart { name: name.to_string(), signature_data: None }).await?;
+
+        loop {
+            let packet = self.recv().await?;
+            match packet.id {
+                EncryptionRequest::ID => {
+                    let Some(account) = account else {
+                        return Err(ClientError::Auth("the server is in online mode".to_string()));
+                    };
+                    let request = packet.parse::<EncryptionRequest>(self.state)?;
+
+                    self.encrypt(account, request).await?;
+                },
+                LoginPluginRequest::ID => {
+                    let request = packet.parse::<LoginPluginRequest>(self.state)?;
+                    // Like vanilla, say we don't understand anything else.
+                    let data = match &self.velocity {
+                        Some((secret, player)) if request.channel == VELOCITY_CHANNEL => Some(RemainingBytes(player.write(secret))),
+                        _ => None,
+                    };
+
+                    self.send(&LoginPluginResponse { message_id: request.message_id, data }).await?;
+                },
+                SetCompression::ID => {
+                    let threshold = packet.parse::<SetCompression>(self.state)?.threshold.0;
+                    self.compression = usize::try_from(threshold).ok();
+                },
+                LoginSuccess::ID => {
+                    self.state = ConnectionState::Play;
+                    return packet.parse(ConnectionState::Login);
+                },
+                id => return Err(ClientError::UnexpectedPacket { state: self.state, expected: LoginSuccess::ID, got: id }),
+            }
+        }
+    }
+
+    /// Answers an Encryption Request the way vanilla clients do: join
+    /// through the session server, then send our secret and switch over.
+    async fn encrypt(&mut self, account: &Account, request: EncryptionRequest) -> Result<(), ClientError> {
+        let secret: [u8; 16] = rand::random();
+        account.join(&auth::server_hash(&request.server_id, &secret, &request.public_key.0)).await?;
+
+        let key = RsaPublicKey::from_public_key_der(&request.public_key.0)
+            .map_err(|e| ClientError::BadPacket(format!("invalid public key; err = {}", e)))?;
+        let encrypt = |data: &[u8]| key.encrypt(&mut rand::thread_rng(), Pkcs1v15Encrypt, data)
+            .map_err(|e| ClientError::BadPacket(format!("failed to encrypt; err = {}", e)));
+        let response = EncryptionResponse {
+            shared_secret: PrefixedArray(encrypt(&secret)?),
+            verification: Verification::VerifyToken(PrefixedArray(encrypt(&request.verify_token.0)?)),
+        };
+
+        self.send(&response).await?;
+
+        // Same as on the server: anything already buffered came in encrypted.
+        let read_ahead = std::mem::take(&mut self.received);
+        self.reader.enable(&secret, &read_ahead);
+        self.cipher = Some(codec::encryptor(&secret));
+
+        Ok(())
+    }
+
+    pub async fn send<T: Serialize + IdentifiedPacket>(&mut self, packet: &T) -> Result<(), ClientError> {
+        let mut data = write_varint(T::ID);
+        data.extend(to_vec(packet)?);
+
+        self.send_raw(data).await
+    }
+
+    /// Sends a packet ID followed by its data, for packets hubby has no struct for.
+    pub async fn send_raw(&mut self, data: Vec<u8>) -> Result<(), ClientError> {
+        let body = match self.compression {
+            Some(threshold) => codec::compress(&data, threshold)?,
+            None => data,
+        };
+
+        let mut frame = write_varint(body.len() as i32);
+        frame.extend(body);
+        if let Some(cipher) = &mut self.cipher {
+            codec::encrypt(cipher, &mut frame);
+        }
+
+        self.unsent.extend(frame);
+        self.flush().await
+    }
+
+    /// Writes out whatever an earlier, cancelled send didn't get to.
+    async fn flush(&mut self) -> Result<(), ClientError> {
+        while !self.unsent.is_empty() {
+            let written = self.writer.write(&self.unsent).await?;
+            if written == 0 {
+                return Err(ClientError::Io(io::ErrorKind::WriteZero.into()));
+            }
+
+            self.unsent.drain(..written);
+        }
+
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Waits for the next packet. Keep Alives are answered and skipped, and
+    /// being disconnected comes back as [`ClientError::Disconnected`].
+    pub async fn recv(&mut self) -> Result<Packet, ClientError> {
+        self.flush().await?;
+
+        loop {
+            let packet = time::timeout(self.timeout, self.read_packet()).await
+                .map_err(|_| ClientError::Timeout)??;
+
+            match (self.state, packet.id) {
+                (ConnectionState::Login, LoginDisconnect::ID) => {
+                    return Err(ClientError::Disconnected(packet.parse::<LoginDisconnect>(self.state)?.reason));
+                },
+                (ConnectionState::Play, Disconnect::ID) => {
+                    return Err(ClientError::Disconnected(packet.parse::<Disconnect>(self.state)?.reason));
+                },
+                (ConnectionState::Play, ClientboundKeepAlive::ID) => {
+                    let id = packet.parse::<ClientboundKeepAlive>(self.state)?.id;
+                    self.send(&ServerboundKeepAlive { id }).await?;
+                },
+                _ => return Ok(packet),
+            }
+        }
+    }
+
+    /// Waits for the next packet, which has to be a `T`.
+    pub async fn expect<T: for<'a> Deserialize<'a> + IdentifiedPacket>(&mut self) -> Result<T, ClientError> {
+        self.recv().await?.parse(self.state)
+    }
+
+    /// Skips packets until a `T` comes in.
+    pub async fn wait_for<T: for<'a> Deserialize<'a> + IdentifiedPacket>(&mut self) -> Result<T, ClientError> {
+        loop {
+            let packet = self.recv().await?;
+            if packet.id == T::ID {
+                return packet.parse(self.state);
+            }
+        }
+    }
+
+    /// Waits for the server to close the connection, failing if anything
+    /// other than a disconnect comes in first.
+    pub async fn expect_closed(&mut self) -> Result<(), ClientError> {
+        match self.recv().await {
+            Ok(packet) => Err(ClientError::BadPacket(format!("expected the connection to close, got packet 0x{:02X}", packet.id))),
+            Err(ClientError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(()),
+            Err(ClientError::Disconnected(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn read_packet(&mut self) -> Result<Packet, ClientError> {
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return self.parse_frame(frame);
+            }
+
+            if self.reader.read_buf(&mut self.received).await? == 0 {
+                return Err(ClientError::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+    }
+
+    /// Takes the next whole frame out of what's been received, if it's all there.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, ClientError> {
+        // MAX_PACKET_LENGTH always fits in 3 bytes.
+        let mut len = 0;
+        let mut len_len = None;
+        for (i, byte) in self.received.iter().take(3).enumerate() {
+            len |= (*byte as i32 & 0x7F) << (7 * i);
+            if byte & 0x80 == 0 {
+                len_len = Some(i + 1);
+                break;
+            }
+        }
+
+        let Some(len_len) = len_len else {
+            return match self.received.len() >= 3 {
+                true => Err(ClientError::BadPacket("packet length too long".to_string())),
+                false => Ok(None),
+            };
+        };
+        if len <= 0 || len > MAX_PACKET_LENGTH {
+            return Err(ClientError::BadPacket(format!("invalid packet length {}", len)));
+        }
+
+        let end = len_len + len as usize;
+        if self.received.len() < end {
+            return Ok(None);
+        }
+
+        let frame = self.received[len_len..end].to_vec();
+        self.received.drain(..end);
+
+        Ok(Some(frame))
+    }
+
+    fn parse_frame(&self, mut data: Vec<u8>) -> Result<Packet, ClientError> {
+        if let Some(threshold) = self.compression {
+            data = codec::decompress(&data, threshold)?;
+        }
+
+        let (id, id_len) = read_varint(&data)
+            .map_err(|e| ClientError::BadPacket(format!("failed to read packet ID; err = {}", e)))?;
+        data.drain(..id_len);
+
+        Ok(Packet { id, data })
+    }
+
+    /// Hangs up, letting the server know nothing more is coming.
+    pub async fn close(mut self) -> Result<(), ClientError> {
+        self.flush().await?;
+        self.writer.shutdown().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{config::{Config, DuplicateLogin, LoginMode}, connection::ConnectionState, packets::{login::offline_uuid, play::{PlayerInfo, ServerboundKeepAlive, SyncPlayerPosition}}, server::Server};
+
+    use super::{Client, ClientError, session::FakeSessionServer};
+
+    fn server(config: Config) -> Arc<Server> {
+        Server::builder().config(config).build().unwrap()
+    }
+
+    fn peer() -> std::net::SocketAddr {
+        "127.0.0.1:50000".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn status_and_ping() {
+        let server = server(Config::default());
+        let mut client = Client::in_memory(&server, peer());
+
+        let status = client.status().await.unwrap();
+        assert_eq!(status["version"]["protocol"], 759);
+        assert_eq!(status["players"]["online"], 0);
+        client.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn offline_login() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        let server = server(config);
+        let mut client = Client::in_memory(&server, peer());
+
+        let profile = client.login_offline("Alex").await.unwrap();
+        assert_eq!(profile.uuid, offline_uuid("Alex").as_u128());
+        assert_eq!(client.state(), ConnectionState::Play);
+
+        let position = client.wait_for::<SyncPlayerPosition>().await.unwrap();
+        assert_eq!(position.y, Config::default().world.spawn.y);
+        assert_eq!(server.players.len(), 1);
+        client.wait_for::<PlayerInfo>().await.unwrap();
+
+        client.send(&ServerboundKeepAlive { id: 1 }).await.unwrap();
+        assert!(matches!(client.recv().await, Err(ClientError::Disconnected(_))));
+    }
+
+    #[tokio::test]
+    async fn duplicate_login() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        let server = server(config.clone());
+
+        let mut first = Client::in_memory(&server, peer());
+        first.login_offline("Alex").await.unwrap();
+        let mut second = Client::in_memory(&server, peer());
+        second.login_offline("alex").await.unwrap();
+
+        let reason = loop {
+            if let Err(e) = first.recv().await {
+                break e.to_string();
+            }
+        };
+        assert!(reason.contains(&config.messages.logged_in_elsewhere), "{}", reason);
+        assert_eq!(server.players.by_name("ALEX").unwrap().name, "alex");
+        assert_eq!(server.players.len(), 1);
+
+        config.login.duplicates = DuplicateLogin::RejectNew;
+        let server = self::server(config.clone());
+        let mut first = Client::in_memory(&server, peer());
+        first.login_offline("Alex").await.unwrap();
+        let mut second = Client::in_memory(&server, peer());
+        let reason = second.login_offline("Alex").await.unwrap_err().to_string();
+        assert!(reason.contains(&config.messages.already_online), "{}", reason);
+        assert_eq!(server.players.by_name("Alex").unwrap().name, "Alex");
+    }
+
+    #[tokio::test]
+    async fn online_login() {
+        let session = FakeSessionServer::start().await.unwrap();
+        let mut config = Config::default();
+        config.login.mode = LoginMode::Online;
+        config.login.session_server = session.url().to_string();
+        config.world.view_distance = 2;
+        let server = server(config);
+
+        let account = session.account("Notch");
+        let mut client = Client::in_memory(&server, peer());
+        let profile = client.login_online(&account).await.unwrap();
+        assert_eq!((profile.uuid, profile.username.as_str()), (account.uuid.as_u128(), "Notch"));
+        client.wait_for::<SyncPlayerPosition>().await.unwrap();
+
+        let mut offline = Client::in_memory(&server, peer());
+        assert!(matches!(offline.login_offline("jeb_").await, Err(ClientError::Auth(_))));
+
+        let mut impostor = session.account("Dinnerbone");
+        impostor.access_token = "stolen".to_string();
+        let mut client = Client::in_memory(&server, peer());
+        assert!(matches!(client.login_online(&impostor).await, Err(ClientError::Auth(_))));
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, io, sync::{Arc, Mutex}};
+
+use serde_json::json;
+use tokio::{io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}, task::JoinHandle};
+use uuid::{Builder, Uuid};
+
+use super::ClientError;
+
+/// A Mojang account to log in with in online mode.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub uuid: Uuid,
+    pub access_token: String,
+    /// Where to join servers, without a trailing slash.
+    pub session_server: String,
+}
+
+impl Account {
+    /// Tells the session server we're joining the server identified by
+    /// `server_hash`, so that server can then check we really are.
+    pub async fn join(&self, server_hash: &str) -> Result<(), ClientError> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/session/minecraft/join", self.session_server))
+            .json(&json!({
+                "accessToken": self.access_token,
+                "selectedProfile": self.uuid.simple().to_string(),
+                "serverId": server_hash,
+            }))
+            .send().await
+            .map_err(|e| ClientError::Auth(e.to_string()))?;
+
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(ClientError::Auth(format!("session server answered {}", response.status()))),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sessions {
+    /// Access tokens to the accounts they're for.
+    accounts: HashMap<String, Account>,
+    /// Usernames to the server hash they last joined with.
+    joined: HashMap<String, String>,
+}
+
+/// Just enough of Mojang's session server to log in to an online mode server
+/// in tests: `join` and `hasJoined`, for accounts made with
+/// [`FakeSessionServer::account`]. Stops when dropped.
+pub struct FakeSessionServer {
+    url: String,
+    sessions: Arc<Mutex<Sessions>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeSessionServer {
+    /// Starts listening on a free local port.
+    pub async fn start() -> io::Result<FakeSessionServer> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let sessions = Arc::new(Mutex::new(Sessions::default()));
+
+        let task = tokio::spawn({
+            let sessions = sessions.clone();
+            async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let sessions = sessions.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(socket, &sessions).await {
+                            eprintln!("fake session server failed to answer; err = {}", e);
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(FakeSessionServer { url, sessions, task })
+    }
+
+    /// What to point `login.session_server` at.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Makes up an account called `name` that can join through this server.
+    pub fn account(&self, name: &str) -> Account {
+        let account = Account {
+            name: name.to_string(),
+            uuid: Builder::from_random_bytes(rand::random()).into_uuid(),
+            access_token: Uuid::from_u128(rand::random()).simple().to_string(),
+            session_server: self.url.clone(),
+        };
+
+        self.sessions.lock().unwrap().accounts.insert(account.access_token.clone(), account.clone());
+
+        account
+    }
+}
+
+impl Drop for FakeSessionServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answers a single HTTP/1.1 request, then closes the connection.
+async fn serve(socket: TcpStream, sessions: &Mutex<Sessions>) -> io::Result<()> {
+    let mut reader = BufReader::new(socket);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).await?;
+        if header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (status, body) = match (method, path) {
+        ("POST", "/session/minecraft/join") => join(sessions, &body),
+        ("GET", "/session/minecraft/hasJoined") => has_joined(sessions, query),
+        _ => ("404 Not Found", String::new()),
+    };
+
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    let mut socket = reader.into_inner();
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+fn join(sessions: &Mutex<Sessions>, body: &[u8]) -> (&'static str, String) {
+    let Ok(request) = serde_json::from_slice::<serde_json::Value>(body) else {
+        return ("400 Bad Request", String::new());
+    };
+
+    let mut sessions = sessions.lock().unwrap();
+    let account = request["accessToken"].as_str().and_then(|token| sessions.accounts.get(token));
+    let (Some(account), Some(server_hash)) = (account, request["serverId"].as_str()) else {
+        return ("403 Forbidden", json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid token." }).to_string());
+    };
+    if request["selectedProfile"].as_str() != Some(&account.uuid.simple().to_string()) {
+        return ("403 Forbidden", json!({ "error": "ForbiddenOperationException", "errorMessage": "Invalid profile." }).to_string());
+    }
+
+    let name = account.name.clone();
+    sessions.joined.insert(name, server_hash.to_string());
+
+    ("204 No Content", String::new())
+}
+
+fn has_joined(sessions: &Mutex<Sessions>, query: &str) -> (&'static str, String) {
+    // Neither usernames nor server hashes have anything in them that would
+    // need percent-decoding.
+    let params: HashMap<_, _> = query.split('&').filter_map(|x| x.split_once('=')).collect();
+    let (Some(name), Some(server_hash)) = (params.get("username"), params.get("serverId")) else {
+        return ("400 Bad Request", String::new());
+    };
+
+    let sessions = sessions.lock().unwrap();
+    if sessions.joined.get(*name).map(String::as_str) != Some(*server_hash) {
+        return ("204 No Content", String::new());
+    }
+
+    let Some(account) = sessions.accounts.values().find(|x| x.name == *name) else {
+        return ("204 No Content", String::new());
+    };
+
+    let profile = json!({
+        "id": account.uuid.simple().to_string(),
+        "name": account.name,
+        "properties": [],
+    });
+
+    ("200 OK", profile.to_string())
+}
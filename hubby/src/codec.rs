@@ -0,0 +1,174 @@
+use std::{io::{self, Read, Write}, pin::Pin, task::{Context, Poll, ready}};
+
+use aes::Aes128;
+use cfb8::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use mc_varint::{read_varint, write_varint};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::packets::HandleError;
+
+pub type Encryptor = cfb8::Encryptor<Aes128>;
+pub type Decryptor = cfb8::Decryptor<Aes128>;
+
+/// Vanilla's limit on how big a packet can be once it's decompressed.
+pub const MAX_DECOMPRESSED_LENGTH: usize = 8388608;
+
+/// The protocol uses the shared secret as both the key and the IV.
+pub fn encryptor(secret: &[u8; 16]) -> Encryptor {
+    Encryptor::new(secret.into(), secret.into())
+}
+
+pub fn decryptor(secret: &[u8; 16]) -> Decryptor {
+    Decryptor::new(secret.into(), secret.into())
+}
+
+/// CFB8 works a byte at a time, so each byte is its own block.
+pub fn encrypt(cipher: &mut Encryptor, data: &mut [u8]) {
+    for byte in data {
+        cipher.encrypt_block_mut(std::slice::from_mut(byte).into());
+    }
+}
+
+pub fn decrypt(cipher: &mut Decryptor, data: &mut [u8]) {
+    for byte in data {
+        cipher.decrypt_block_mut(std::slice::from_mut(byte).into());
+    }
+}
+
+/// Decrypts everything read through it once [`CipherReader::enable`] is called.
+pub struct CipherReader<R> {
+    inner: R,
+    cipher: Option<Decryptor>,
+    /// Bytes that were read ahead of turning encryption on, already decrypted.
+    leftover: Vec<u8>,
+}
+
+impl<R> CipherReader<R> {
+    pub fn new(inner: R) -> Self {
+        CipherReader { inner, cipher: None, leftover: Vec::new() }
+    }
+
+    /// Turns on decryption. Whatever the caller already read past the point
+    /// where encryption starts has to be handed back as `read_ahead`.
+    pub fn enable(&mut self, secret: &[u8; 16], read_ahead: &[u8]) {
+        let mut cipher = decryptor(secret);
+
+        let mut leftover = read_ahead.to_vec();
+        decrypt(&mut cipher, &mut leftover);
+        leftover.append(&mut self.leftover);
+
+        self.leftover = leftover;
+        self.cipher = Some(cipher);
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CipherReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.leftover.is_empty() {
+            let len = this.leftover.len().min(buf.remaining());
+            buf.put_slice(&this.leftover[..len]);
+            this.leftover.drain(..len);
+
+            return Poll::Ready(Ok(()));
+        }
+
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        if let Some(cipher) = &mut this.cipher {
+            decrypt(cipher, &mut buf.filled_mut()[start..]);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Turns a packet ID and its data into the body of a compressed frame: the
+/// uncompressed length (0 if it's under the threshold and left as it is),
+/// followed by the data.
+pub fn compress(packet: &[u8], threshold: usize) -> io::Result<Vec<u8>> {
+    if packet.len() < threshold {
+        let mut body = write_varint(0);
+        body.extend(packet);
+
+        return Ok(body);
+    }
+
+    let mut encoder = ZlibEncoder::new(write_varint(packet.len() as i32), Compression::default());
+    encoder.write_all(packet)?;
+
+    encoder.finish()
+}
+
+/// Undoes [`compress`], holding the client to the same rules vanilla does.
+pub fn decompress(body: &[u8], threshold: usize) -> Result<Vec<u8>, HandleError> {
+    let (len, len_len) = read_varint(body)
+        .map_err(|e| HandleError::BadPacket(format!("failed to read data length; err = {}", e)))?;
+    let body = &body[len_len..];
+
+    if len == 0 {
+        if body.len() >= threshold {
+            return Err(HandleError::BadPacket(format!("uncompressed packet of length {} is over the threshold", body.len())));
+        }
+
+        return Ok(body.to_vec());
+    }
+
+    if len < 0 || (len as usize) < threshold || len as usize > MAX_DECOMPRESSED_LENGTH {
+        return Err(HandleError::BadPacket(format!("invalid data length {}", len)));
+    }
+
+    let mut packet = Vec::with_capacity(len as usize);
+    ZlibDecoder::new(body).take(len as u64 + 1).read_to_end(&mut packet)
+        .map_err(|e| HandleError::BadPacket(format!("failed to decompress packet; err = {}", e)))?;
+
+    if packet.len() != len as usize {
+        return Err(HandleError::BadPacket(format!("packet decompressed to {} bytes instead of {}", packet.len(), len)));
+    }
+
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::{CipherReader, compress, decompress, encrypt, encryptor};
+
+    #[test]
+    fn compression_round_trip() {
+        let small = [0x01, 0x02, 0x03];
+        let big = [0x42; 1000];
+
+        assert_eq!(compress(&small, 256).unwrap(), [0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(decompress(&compress(&small, 256).unwrap(), 256).unwrap(), small);
+        assert!(compress(&big, 256).unwrap().len() < big.len());
+        assert_eq!(decompress(&compress(&big, 256).unwrap(), 256).unwrap(), big);
+
+        // Below the threshold, but compressed anyway.
+        assert!(decompress(&compress(&small, 0).unwrap(), 256).is_err());
+        // Over the threshold, but not compressed.
+        assert!(decompress(&compress(&big, 2000).unwrap(), 256).is_err());
+    }
+
+    #[tokio::test]
+    async fn cipher_reader_decrypts_read_ahead() {
+        let secret = [7; 16];
+        let mut data = *b"plain text, then the encrypted part";
+        encrypt(&mut encryptor(&secret), &mut data[17..]);
+
+        let (plain, encrypted) = data.split_at(17);
+        let (read_ahead, rest) = encrypted.split_at(5);
+
+        let mut reader = CipherReader::new(rest);
+        reader.enable(&secret, read_ahead);
+
+        let mut decrypted = String::from_utf8(plain.to_vec()).unwrap();
+        reader.read_to_string(&mut decrypted).await.unwrap();
+
+        assert_eq!(decrypted, "plain text, then the encrypted part");
+    }
+}
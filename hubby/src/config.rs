@@ -0,0 +1,979 @@
+use std::{fmt::{self, Display}, fs, io, net::{IpAddr, Ipv6Addr, SocketAddr}, path::{Path, PathBuf}, time::Duration};
+
+use base64::Engine;
+use serde::{Deserialize, Deserializer};
+
+use crate::{connection::Timeouts, rate_limit::RateLimit, world::{Dimension, GameMode}};
+
+pub const DEFAULT_PATH: &str = "hubby.toml";
+
+/// Written out on first run, so it doubles as the documentation for every
+/// setting. Has to stay in sync with the `Default` impls below.
+pub const DEFAULT_CONFIG: &str = r#"# Hubby configuration. Delete this file to get a fresh copy with the defaults.
+# Changes are picked up while running (or on the `reload` console command),
+# except for the ones marked as needing a restart.
+
+[network]
+# Address to listen for Minecraft clients on. Needs a restart.
+bind = "0.0.0.0:2346"
+# Packets at least this many bytes long get compressed. -1 turns compression off.
+compression_threshold = 256
+# Load balancers that start every connection with a PROXY protocol (v1 or v2)
+# header. Connections from these are taken to be from the address in it, for
+# logging and rate limits. Connections from anywhere else are left as they are.
+proxy_protocol = []
+
+# More addresses to listen on besides `bind`, each with its own options. These
+# need a restart too. The address is either an IP address and port, or "unix:"
+# followed by the path of a Unix domain socket to create, for a proxy running
+# on the same machine. Connections over those look like they're from 127.0.0.1.
+# IPv6 addresses take IPv4 connections as well, unless `ipv6_only` is set.
+# `proxy_protocol` (true or false) overrides whether connections here start
+# with a PROXY header, whoever they're from. `forwarding` overrides the
+# [forwarding] mode for players connecting here.
+# [[network.listeners]]
+# address = "[::]:2346"
+# [[network.listeners]]
+# address = "unix:/run/hubby/proxy.sock"
+# proxy_protocol = false
+# forwarding = "velocity"
+
+[status]
+# Shown under the server's name in the server list. Supports § formatting codes.
+motd = "Hubby"
+max_players = 100
+# Shown to clients on a version we don't support.
+version_name = "1.19"
+# A 64x64 PNG to show as the server icon, relative to this file. Empty for none.
+favicon = ""
+# "hub" shows the players on the hub. "network" adds the players and slots of
+# every backend that's up, and samples players from all of them.
+player_count = "hub"
+
+[login]
+# "offline" takes clients at their word, "online" checks them with the session server.
+# Both of these need a restart.
+mode = "offline"
+session_server = "https://sessionserver.mojang.com"
+# What to do when someone logs in while already online, going by UUID, or by
+# name as well in offline mode. "kick_old" disconnects the session they had,
+# "reject_new" turns the new one away.
+duplicates = "kick_old"
+
+[limits]
+# How many connections can be open at once, across all addresses.
+max_connections = 1024
+# Count whole /24 (IPv4) or /64 (IPv6) subnets as one address for rate limiting.
+group_subnets = false
+# Each address gets `burst` attempts back to back, then earns one back every `refill` seconds.
+connections = { burst = 5, refill = 2 }
+logins = { burst = 3, refill = 10 }
+
+[limits.timeouts]
+# Seconds a connection gets to make it through each step before playing.
+handshake = 5
+status = 10
+login = 30
+# Seconds a single packet gets to arrive once it has started arriving.
+frame = 10
+# Seconds to wait on shutdown for players to be disconnected and everything
+# else to wrap up, before exiting anyway.
+shutdown = 10
+
+[world]
+# "minecraft:overworld", "minecraft:the_nether" or "minecraft:the_end".
+dimension = "minecraft:overworld"
+# "survival", "creative", "adventure" or "spectator".
+gamemode = "adventure"
+# Chunks, from 2 to 32.
+view_distance = 8
+# Where players appear when they join.
+spawn = { x = 0.5, y = 64, z = 0.5, yaw = 0, pitch = 0 }
+
+[tab_list]
+# Players on the hub see each other in the tab list. In proxy mode, the tab
+# list is left to the backends.
+# Shown above and below it: either plain text, where § formatting codes and
+# \n for new lines work, or a JSON text component. {online} and {max} are the
+# hub's player count and slots, {player} and {ping} the name and latency (in
+# milliseconds) of whoever's looking. Leave both empty for neither.
+header = ""
+footer = ""
+# Seconds between refreshing the header and footer.
+refresh = 5
+
+[chat]
+# How players' messages look to everyone else on the hub. {player} is who sent
+# it and {message} what they said. Supports § formatting codes. In proxy mode,
+# chat goes to the backends instead.
+format = "<{player}> {message}"
+# Whether to print what players say to the console.
+log = true
+
+[backends]
+# Seconds between asking each game server behind the hub for its status, and
+# how long one gets to answer before it's considered down.
+poll_interval = 5
+timeout = 3
+# Add one of these for every game server. The address can be a hostname.
+# [[backends.servers]]
+# name = "survival"
+# address = "127.0.0.1:25566"
+
+[proxy]
+# Instead of keeping players on the hub, send them on to the `lobby` backend
+# once they've logged in, and relay everything in between.
+enabled = false
+lobby = ""
+# How backends learn who the player is. "none" only passes their name, so
+# backends see offline mode UUIDs. "legacy" also passes their IP, UUID and
+# skin like BungeeCord does, for backends with `bungeecord: true` set.
+# Either way, backends have to be in offline mode.
+forwarding = "none"
+
+[forwarding]
+# For when the hub is itself behind a proxy: whether the proxy passes on
+# players' real IPs, UUIDs and skins. With it on, players can only log in
+# through the proxy, and login.mode has to stay "offline" since the proxy has
+# already checked them.
+# "legacy" is BungeeCord's, which needs `ip_forward: true` in its config. Only
+# the addresses in `trusted_proxies` are taken at their word.
+# "velocity" is Velocity's modern forwarding, which proves it's the proxy with
+# `secret`, the contents of its forwarding.secret file.
+mode = "none"
+trusted_proxies = ["127.0.0.1", "::1"]
+secret = ""
+
+# Virtual hosts, for running several networks behind one address. Players are
+# matched by the address they typed to connect, against each host's pattern in
+# order, where * stands for anything (so "*.example.com" matches every
+# subdomain). The first match picks what they see in the server list and, in
+# proxy mode, where they're sent. Anything left out falls back to the settings
+# above, as does everyone who matches no host.
+# [[hosts]]
+# pattern = "minigames.example.com"
+# motd = "Minigames!"
+# favicon = "minigames.png"
+# lobby = "minigames"
+
+[messages]
+# What players are told when they're turned away. {version} is the version the hub runs.
+outdated_client = "Outdated client! Please use {version}"
+outdated_server = "Outdated server! I'm still on {version}"
+connection_throttled = "Connection throttled! Please wait before reconnecting."
+login_throttled = "You are logging in too fast, try again later."
+# What clients are told when limits.max_connections are already open.
+server_full = "The server is full, please try again later."
+failed_to_verify = "Failed to verify username!"
+# Proxy mode only: what players are told when no backend can take them.
+backend_unavailable = "Couldn't connect you to a server, please try again later."
+# Forwarding only: what players are told when they didn't come through a trusted proxy.
+not_forwarded = "Please connect through the proxy."
+# What players are told when the same account logs in again: the old session
+# with login.duplicates = "kick_old", the new one with "reject_new".
+logged_in_elsewhere = "You logged in from another location"
+already_online = "You are already logged in."
+# The answer to commands, since the hub doesn't have any.
+unknown_command = "Unknown command."
+# What everyone is told when the hub shuts down, on Ctrl-C, SIGTERM or `stop`.
+shutting_down = "The server is shutting down."
+"#;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+    pub status: StatusConfig,
+    pub login: LoginConfig,
+    pub limits: LimitsConfig,
+    pub world: WorldConfig,
+    pub tab_list: TabListConfig,
+    pub chat: ChatConfig,
+    pub backends: BackendsConfig,
+    pub proxy: ProxyConfig,
+    pub forwarding: ForwardingConfig,
+    pub hosts: Vec<HostConfig>,
+    pub messages: MessagesConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub bind: SocketAddr,
+    pub compression_threshold: i32,
+    /// Addresses trusted to send a PROXY protocol header.
+    pub proxy_protocol: Vec<IpAddr>,
+    pub listeners: Vec<ListenerConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub address: ListenAddress,
+    #[serde(default)]
+    pub ipv6_only: bool,
+    pub proxy_protocol: Option<bool>,
+    pub forwarding: Option<Forwarding>,
+}
+
+impl ListenerConfig {
+    pub fn options(&self) -> ListenerOptions {
+        ListenerOptions { proxy_protocol: self.proxy_protocol, forwarding: self.forwarding }
+    }
+}
+
+/// Where to take connections from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Display for ListenAddress {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => write!(formatter, "{}", addr),
+            ListenAddress::Unix(path) => write!(formatter, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ListenAddress {
+    fn deserialize<D>(deserializer: D) -> Result<ListenAddress, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let address = String::deserialize(deserializer)?;
+        match address.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(ListenAddress::Unix(path.into())),
+            Some(_) => Err(serde::de::Error::custom("a Unix socket needs a path")),
+            None => address.parse().map(ListenAddress::Tcp)
+                .map_err(|_| serde::de::Error::custom(format!("{:?} is neither ip:port nor unix:path", address))),
+        }
+    }
+}
+
+/// What a listener does differently from the rest of the config, for
+/// connections it takes. Unset means the same as everywhere else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ListenerOptions {
+    /// Whether connections start with a PROXY protocol header, instead of
+    /// going by `network.proxy_protocol`.
+    pub proxy_protocol: Option<bool>,
+    /// Instead of `forwarding.mode`.
+    pub forwarding: Option<Forwarding>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            bind: ([0, 0, 0, 0], 2346).into(),
+            compression_threshold: 256,
+            proxy_protocol: vec![],
+            listeners: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusConfig {
+    pub motd: String,
+    pub max_players: u32,
+    pub version_name: String,
+    pub favicon: PathBuf,
+    pub player_count: PlayerCount,
+}
+
+/// Which players the status response counts.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerCount {
+    Hub,
+    Network,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            motd: "Hubby".to_string(),
+            max_players: 100,
+            version_name: "1.19".to_string(),
+            favicon: PathBuf::new(),
+            player_count: PlayerCount::Hub,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LoginMode {
+    Offline,
+    Online,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    pub mode: LoginMode,
+    pub session_server: String,
+    pub duplicates: DuplicateLogin,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        LoginConfig {
+            mode: LoginMode::Offline,
+            session_server: "https://sessionserver.mojang.com".to_string(),
+            duplicates: DuplicateLogin::KickOld,
+        }
+    }
+}
+
+/// Which session wins when a player logs in twice.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateLogin {
+    KickOld,
+    RejectNew,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_connections: usize,
+    pub group_subnets: bool,
+    pub connections: RateLimit,
+    pub logins: RateLimit,
+    pub timeouts: Timeouts,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_connections: 1024,
+            group_subnets: false,
+            connections: RateLimit { burst: 5, refill: Duration::from_secs(2) },
+            logins: RateLimit { burst: 3, refill: Duration::from_secs(10) },
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub dimension: Dimension,
+    pub gamemode: GameMode,
+    pub view_distance: u8,
+    pub spawn: Location,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            dimension: Dimension::Overworld,
+            gamemode: GameMode::Adventure,
+            view_distance: 8,
+            spawn: Location { x: 0.5, y: 64.0, z: 0.5, yaw: 0.0, pitch: 0.0 },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TabListConfig {
+    /// Plain text or a JSON text component, with placeholders.
+    pub header: String,
+    pub footer: String,
+    #[serde(deserialize_with = "seconds")]
+    pub refresh: Duration,
+}
+
+impl Default for TabListConfig {
+    fn default() -> Self {
+        TabListConfig {
+            header: String::new(),
+            footer: String::new(),
+            refresh: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    /// With `{player}` and `{message}` placeholders.
+    pub format: String,
+    /// Whether chat messages and commands are printed to the console.
+    pub log: bool,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig {
+            format: "<{player}> {message}".to_string(),
+            log: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendsConfig {
+    #[serde(deserialize_with = "seconds")]
+    pub poll_interval: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub timeout: Duration,
+    pub servers: Vec<BackendConfig>,
+}
+
+impl Default for BackendsConfig {
+    fn default() -> Self {
+        BackendsConfig {
+            poll_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(3),
+            servers: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BackendConfig {
+    pub name: String,
+    /// `host:port`, where the host can be a name or an IP.
+    pub address: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    pub enabled: bool,
+    /// The name of the backend players are sent to first.
+    pub lobby: String,
+    pub forwarding: Forwarding,
+}
+
+/// How a proxy passes a player's identity on to the server behind it, be
+/// that hubby to its backends or another proxy to hubby.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Forwarding {
+    #[default]
+    None,
+    /// BungeeCord's, in the handshake's server address.
+    Legacy,
+    /// Velocity's, in a login plugin response. Only taken from proxies.
+    Velocity,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForwardingConfig {
+    /// How players' identities arrive from a proxy in front of the hub.
+    pub mode: Forwarding,
+    /// Legacy only.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Velocity only.
+    pub secret: String,
+}
+
+impl Default for ForwardingConfig {
+    fn default() -> Self {
+        ForwardingConfig {
+            mode: Forwarding::None,
+            trusted_proxies: vec![[127, 0, 0, 1].into(), Ipv6Addr::LOCALHOST.into()],
+            secret: String::new(),
+        }
+    }
+}
+
+impl ForwardingConfig {
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.contains(&ip.to_canonical())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// A hostname, where `*` matches any run of characters. Lowercased as
+    /// it's read, since hostnames are matched without regard to case.
+    #[serde(deserialize_with = "lowercase")]
+    pub pattern: String,
+    pub motd: Option<String>,
+    pub favicon: Option<PathBuf>,
+    /// The backend players are sent to first, in proxy mode.
+    pub lobby: Option<String>,
+}
+
+impl HostConfig {
+    /// Whether `host`, which has to be lowercase already (as
+    /// [`crate::packets::handshaking::virtual_host`] makes it), matches.
+    pub fn matches(&self, host: &str) -> bool {
+        wildcard_match(self.pattern.as_bytes(), host.as_bytes())
+    }
+
+    pub fn load_favicon(&self) -> Result<Option<String>, ConfigError> {
+        match &self.favicon {
+            Some(path) => read_favicon(path, "hosts.favicon"),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Matches greedily, going back only to the last `*` on a mismatch, so it
+/// never backtracks more than once per character of `text`.
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The last `*` seen, and where in `text` it has matched up to.
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match star {
+                // Have the `*` take one more character, and carry on after it.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessagesConfig {
+    pub outdated_client: String,
+    pub outdated_server: String,
+    pub connection_throttled: String,
+    pub login_throttled: String,
+    pub server_full: String,
+    pub failed_to_verify: String,
+    pub backend_unavailable: String,
+    pub not_forwarded: String,
+    pub logged_in_elsewhere: String,
+    pub already_online: String,
+    pub unknown_command: String,
+    pub shutting_down: String,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        MessagesConfig {
+            outdated_client: "Outdated client! Please use {version}".to_string(),
+            outdated_server: "Outdated server! I'm still on {version}".to_string(),
+            connection_throttled: "Connection throttled! Please wait before reconnecting.".to_string(),
+            login_throttled: "You are logging in too fast, try again later.".to_string(),
+            server_full: "The server is full, please try again later.".to_string(),
+            failed_to_verify: "Failed to verify username!".to_string(),
+            backend_unavailable: "Couldn't connect you to a server, please try again later.".to_string(),
+            not_forwarded: "Please connect through the proxy.".to_string(),
+            logged_in_elsewhere: "You logged in from another location".to_string(),
+            already_online: "You are already logged in.".to_string(),
+            unknown_command: "Unknown command.".to_string(),
+            shutting_down: "The server is shutting down.".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(&'static str, String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(formatter, "couldn't access {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(formatter, "couldn't parse {}: {}", path.display(), e),
+            ConfigError::Invalid(key, e) => write!(formatter, "invalid config value for `{}`: {}", key, e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::Parse(_, e) => Some(e),
+            ConfigError::Invalid(..) => None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the config at `path`, writing out the defaults
+    /// first if there's nothing there yet. Only for startup; reloads use
+    /// [`Config::read`], so a deleted file doesn't bring the defaults back.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        if !path.exists() {
+            println!("no config found, writing the defaults to {}", path.display());
+            fs::write(path, DEFAULT_CONFIG).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        }
+
+        Config::read(path)
+    }
+
+    /// Reads and validates the config at `path`, which has to exist.
+    pub fn read(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        // Relative paths in the config are relative to the config itself.
+        if let Some(dir) = path.parent() {
+            if !config.status.favicon.as_os_str().is_empty() {
+                config.status.favicon = dir.join(&config.status.favicon);
+            }
+            for favicon in config.hosts.iter_mut().filter_map(|host| host.favicon.as_mut()) {
+                *favicon = dir.join(&favicon);
+            }
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Checks everything the types alone don't rule out.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.network.compression_threshold < -1 {
+            return Err(ConfigError::Invalid("network.compression_threshold", "must be -1 (off) or more".to_string()));
+        }
+
+        if self.login.mode == LoginMode::Online && !self.login.session_server.starts_with("http://") && !self.login.session_server.starts_with("https://") {
+            return Err(ConfigError::Invalid("login.session_server", format!("{:?} is not an http(s) URL", self.login.session_server)));
+        }
+
+        if self.limits.max_connections == 0 {
+            return Err(ConfigError::Invalid("limits.max_connections", "must be at least 1".to_string()));
+        }
+
+        for (key, limit) in [("limits.connections", self.limits.connections), ("limits.logins", self.limits.logins)] {
+            if limit.burst == 0 {
+                return Err(ConfigError::Invalid(key, "burst must be at least 1".to_string()));
+            }
+        }
+
+        let timeouts = self.limits.timeouts;
+        for (key, timeout) in [
+            ("limits.timeouts.handshake", timeouts.handshake),
+            ("limits.timeouts.status", timeouts.status),
+            ("limits.timeouts.login", timeouts.login),
+            ("limits.timeouts.frame", timeouts.frame),
+            ("limits.timeouts.shutdown", timeouts.shutdown),
+        ] {
+            if timeout.is_zero() {
+                return Err(ConfigError::Invalid(key, "must be more than 0 seconds".to_string()));
+            }
+        }
+
+        if !(2..=32).contains(&self.world.view_distance) {
+            return Err(ConfigError::Invalid("world.view_distance", format!("{} is not between 2 and 32", self.world.view_distance)));
+        }
+
+        let spawn = self.world.spawn;
+        let dimension = self.world.dimension;
+        if !spawn.x.is_finite() || !spawn.z.is_finite() || spawn.x.abs() > 29_999_984.0 || spawn.z.abs() > 29_999_984.0 {
+            return Err(ConfigError::Invalid("world.spawn", "x and z have to be inside the world border".to_string()));
+        }
+        if !spawn.y.is_finite() || spawn.y < dimension.min_y() as f64 || spawn.y >= (dimension.min_y() + dimension.height()) as f64 {
+            return Err(ConfigError::Invalid("world.spawn", format!(
+                "y has to be within {}'s build height ({} to {})",
+                dimension.name(), dimension.min_y(), dimension.min_y() + dimension.height()
+            )));
+        }
+
+        if self.tab_list.refresh.is_zero() {
+            return Err(ConfigError::Invalid("tab_list.refresh", "must be more than 0 seconds".to_string()));
+        }
+
+        let backends = &self.backends;
+        if backends.poll_interval.is_zero() || backends.timeout.is_zero() {
+            return Err(ConfigError::Invalid("backends", "poll_interval and timeout must be more than 0 seconds".to_string()));
+        }
+        for (i, backend) in backends.servers.iter().enumerate() {
+            if backend.name.is_empty() || backends.servers[..i].iter().any(|x| x.name == backend.name) {
+                return Err(ConfigError::Invalid("backends.servers", format!("every server needs a unique name, {:?} isn't", backend.name)));
+            }
+            if !backend.address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()) {
+                return Err(ConfigError::Invalid("backends.servers", format!("{}'s address {:?} isn't host:port", backend.name, backend.address)));
+            }
+        }
+
+        if self.proxy.enabled && !backends.servers.iter().any(|x| x.name == self.proxy.lobby) {
+            return Err(ConfigError::Invalid("proxy.lobby", format!("{:?} isn't one of the backends", self.proxy.lobby)));
+        }
+
+        #[cfg(not(unix))]
+        if self.network.listeners.iter().any(|x| matches!(x.address, ListenAddress::Unix(_))) {
+            return Err(ConfigError::Invalid("network.listeners", "Unix sockets aren't supported on this platform".to_string()));
+        }
+        for (i, listener) in self.network.listeners.iter().enumerate() {
+            if self.network.listeners[..i].iter().any(|x| x.address == listener.address) || listener.address == ListenAddress::Tcp(self.network.bind) {
+                return Err(ConfigError::Invalid("network.listeners", format!("{} is listened on twice", listener.address)));
+            }
+        }
+
+        // Every forwarding mode players might connect with.
+        let modes = || std::iter::once(self.forwarding.mode).chain(self.network.listeners.iter().filter_map(|x| x.forwarding));
+
+        if modes().any(|mode| mode != Forwarding::None) && self.login.mode == LoginMode::Online {
+            return Err(ConfigError::Invalid("forwarding.mode", "the proxy checks players itself, so login.mode has to be \"offline\"".to_string()));
+        }
+
+        if modes().any(|mode| mode == Forwarding::Velocity) && self.forwarding.secret.is_empty() {
+            return Err(ConfigError::Invalid("forwarding.secret", "has to be set for Velocity forwarding".to_string()));
+        }
+        if self.proxy.forwarding == Forwarding::Velocity {
+            return Err(ConfigError::Invalid("proxy.forwarding", "can only be \"none\" or \"legacy\"".to_string()));
+        }
+
+        for host in &self.hosts {
+            if host.pattern.is_empty() {
+                return Err(ConfigError::Invalid("hosts.pattern", "can't be empty".to_string()));
+            }
+            if let Some(lobby) = &host.lobby {
+                if !backends.servers.iter().any(|x| x.name == *lobby) {
+                    return Err(ConfigError::Invalid("hosts.lobby", format!("{:?} isn't one of the backends", lobby)));
+                }
+            }
+        }
+
+        self.load_favicons()?;
+
+        Ok(())
+    }
+
+    /// The first virtual host matching `host`, if any do.
+    pub fn host(&self, host: &str) -> Option<&HostConfig> {
+        self.hosts.iter().find(|x| x.matches(host))
+    }
+
+    /// Where players who connected through `host` are sent first in proxy mode.
+    pub fn lobby(&self, host: &str) -> &str {
+        self.host(host).and_then(|x| x.lobby.as_deref()).unwrap_or(&self.proxy.lobby)
+    }
+
+    /// The server's favicon, then each host's, in order.
+    pub fn load_favicons(&self) -> Result<(Option<String>, Vec<Option<String>>), ConfigError> {
+        let hosts = self.hosts.iter().map(HostConfig::load_favicon).collect::<Result<_, _>>()?;
+
+        Ok((self.status.load_favicon()?, hosts))
+    }
+
+    /// Puts back the `running` values of settings that only take effect on
+    /// a restart, returning the names of the ones that had been changed.
+    /// The result has to be validated again, since the new settings may not
+    /// go with the old ones.
+    pub fn keep_restart_only(&mut self, running: &Config) -> Vec<&'static str> {
+        let mut changed = vec![];
+
+        if self.network.bind != running.network.bind {
+            changed.push("network.bind");
+            self.network.bind = running.network.bind;
+        }
+        if self.network.listeners != running.network.listeners {
+            changed.push("network.listeners");
+            self.network.listeners = running.network.listeners.clone();
+        }
+        if self.login != running.login {
+            changed.push("login");
+            self.login = running.login.clone();
+        }
+
+        changed
+    }
+}
+
+impl StatusConfig {
+    /// Reads the favicon as the data URI the status response wants, if one is set.
+    pub fn load_favicon(&self) -> Result<Option<String>, ConfigError> {
+        if self.favicon.as_os_str().is_empty() {
+            return Ok(None);
+        }
+
+        read_favicon(&self.favicon, "status.favicon")
+    }
+}
+
+/// Reads the PNG at `path` as the data URI the status response wants.
+fn read_favicon(path: &Path, key: &'static str) -> Result<Option<String>, ConfigError> {
+    let png = fs::read(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+
+    // The signature, then the IHDR chunk, which always comes first and
+    // starts with the width and height.
+    if png.len() < 24 || &png[..8] != b"\x89PNG\r\n\x1a\n" || &png[12..16] != b"IHDR" {
+        return Err(ConfigError::Invalid(key, format!("{} is not a PNG", path.display())));
+    }
+
+    let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+    if (width, height) != (64, 64) {
+        return Err(ConfigError::Invalid(key, format!("has to be 64x64 pixels, not {}x{}", width, height)));
+    }
+
+    Ok(Some(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png))))
+}
+
+/// Reads a string, lowercased.
+pub fn lowercase<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(String::deserialize(deserializer)?.to_ascii_lowercase())
+}
+
+/// Reads a duration given as a (possibly fractional) number of seconds.
+pub fn seconds<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let secs = f64::deserialize(deserializer)?;
+
+    Duration::try_from_secs_f64(secs)
+        .map_err(|_| serde::de::Error::custom(format!("{} is not a valid number of seconds", secs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigError, LoginMode, DEFAULT_CONFIG};
+
+    #[test]
+    fn default_config_matches_defaults() {
+        let config: Config = toml::from_str(DEFAULT_CONFIG).unwrap();
+
+        assert_eq!(config, Config::default());
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn missing_values_use_defaults() {
+        let config: Config = toml::from_str("[status]\nmotd = \"Other\"").unwrap();
+
+        assert_eq!(config.status.motd, "Other");
+        assert_eq!(config.status.max_players, 100);
+        assert_eq!(config.network, Config::default().network);
+    }
+
+    #[test]
+    fn restart_only_settings_are_kept() {
+        let running = Config::default();
+        let mut config: Config = toml::from_str("[network]\nbind = \"127.0.0.1:1\"\n[status]\nmotd = \"New\"").unwrap();
+
+        assert_eq!(config.keep_restart_only(&running), ["network.bind"]);
+        assert_eq!(config.network.bind, running.network.bind);
+        assert_eq!(config.status.motd, "New");
+
+        // Forwarding can't be turned on while the running hub is in online mode.
+        let mut running = Config::default();
+        running.login.mode = LoginMode::Online;
+        let mut config: Config = toml::from_str("[forwarding]\nmode = \"legacy\"").unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.keep_restart_only(&running), ["login"]);
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid("forwarding.mode", _))));
+    }
+
+    #[test]
+    fn only_startup_writes_the_defaults() {
+        let path = std::env::temp_dir().join(format!("hubby-{}.toml", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(Config::read(&path), Err(ConfigError::Io(..))));
+        assert!(!path.exists());
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        assert!(Config::read(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(toml::from_str::<Config>("[network]\nbind = \"nope\"").is_err());
+        assert!(toml::from_str::<Config>("[world]\ngamemode = \"hardcore\"").is_err());
+        assert!(toml::from_str::<Config>("[limits]\nlogins = { burst = 1, refill = -1 }").is_err());
+        assert!(toml::from_str::<Config>("[status]\nmtod = \"typo\"").is_err());
+
+        let config: Config = toml::from_str("[world]\nview_distance = 40").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid("world.view_distance", _))));
+
+        let config: Config = toml::from_str("[[backends.servers]]\nname = \"a\"\naddress = \"localhost\"").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid("backends.servers", _))));
+
+        let config: Config = toml::from_str("[world]\ndimension = \"minecraft:the_nether\"\nspawn = { x = 0, y = -10, z = 0 }").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid("world.spawn", _))));
+
+        let config: Config = toml::from_str("[[hosts]]\npattern = \"*.example.com\"\nlobby = \"nowhere\"").unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid("hosts.lobby", _))));
+    }
+
+    #[test]
+    fn matches_hosts() {
+        let config: Config = toml::from_str(r#"
+            [proxy]
+            lobby = "main"
+            [[backends.servers]]
+            name = "main"
+            address = "127.0.0.1:25566"
+            [[backends.servers]]
+            name = "minigames"
+            address = "127.0.0.1:25567"
+            [[hosts]]
+            pattern = "mc.example.com"
+            motd = "Main"
+            [[hosts]]
+            pattern = "*.Example.com"
+            lobby = "minigames"
+        "#).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.host("mc.example.com").unwrap().motd.as_deref(), Some("Main"));
+        assert_eq!(config.lobby("mc.example.com"), "main");
+        assert_eq!(config.lobby("games.example.com"), "minigames");
+        assert_eq!(config.lobby("a.b.example.com"), "minigames");
+        assert!(config.host("example.com").is_none());
+        assert_eq!(config.lobby("localhost"), "main");
+    }
+
+    #[test]
+    fn wildcard_match() {
+        use super::wildcard_match;
+        assert!(wildcard_match(b"*.*.example.com", b"a.b.example.com"));
+        assert!(wildcard_match(b"*.*.example.com", b"a.b.c.example.com"));
+        assert!(!wildcard_match(b"*.*.example.com", b"a.example.com"));
+        assert!(wildcard_match(b"mc*", b"mc"));
+        assert!(wildcard_match(b"**", b""));
+        assert!(!wildcard_match(b"", b"a"));
+
+        // Takes linear time, however far off the text is.
+        let text = ".".repeat(80_000);
+        assert!(!wildcard_match(b"*.*.example.com", text.as_bytes()));
+    }
+}
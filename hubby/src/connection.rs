@@ -1,10 +1,12 @@
-use std::io::ErrorKind;
+use std::{io::{self, ErrorKind}, net::SocketAddr, sync::{Arc, Mutex, atomic::Ordering}, time::Duration};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_mcje::to_vec;
-use tokio::{net::TcpStream, io::{AsyncReadExt, AsyncWriteExt}};
-use crate::{varint::*, packets::{self, HandleError, IdentifiedPacket}};
+use tokio::{io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter}, sync::mpsc, task::JoinHandle, time::{self, Instant}};
+use tokio_util::sync::CancellationToken;
+use crate::{varint::*, codec::{self, CipherReader}, config::{self, Config, Forwarding, ListenerOptions}, keep_alive::{self, KeepAlive}, player::Player, server::{Server, Traffic}, packets::{self, HandleError, IdentifiedPacket, login::{ForwardedPlayer, LoginDisconnect, PendingLogin}, play::{Disconnect, ServerboundKeepAlive}}, proxy::ProxyHandle, proxy_protocol};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ConnectionState {
     Handshaking,
     Status,
@@ -12,86 +14,712 @@ pub enum ConnectionState {
     Play
 }
 
-pub struct Connection<'a> {
-    pub socket: &'a mut TcpStream,
-    pub state: ConnectionState,
+/// The largest packet the vanilla protocol allows, which is the most that
+/// fits in a 3-byte VarInt length.
+pub const MAX_PACKET_LENGTH: i32 = 2097151;
+
+/// How long a connection gets to make its way through each state before
+/// Play, so one that stalls (or trickles in a byte at a time) can't keep a
+/// task and its buffers around forever. Play is covered by keep-alives.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// From accepting the connection to receiving the handshake.
+    #[serde(deserialize_with = "config::seconds")]
+    pub handshake: Duration,
+    /// From the handshake to the end of the status exchange.
+    #[serde(deserialize_with = "config::seconds")]
+    pub status: Duration,
+    /// From the handshake to login finishing.
+    #[serde(deserialize_with = "config::seconds")]
+    pub login: Duration,
+    /// For a single packet, from its first byte to its last, in any state.
+    #[serde(deserialize_with = "config::seconds")]
+    pub frame: Duration,
+    /// From shutting down to giving up on connections closing by themselves.
+    #[serde(deserialize_with = "config::seconds")]
+    pub shutdown: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            handshake: Duration::from_secs(5),
+            status: Duration::from_secs(10),
+            login: Duration::from_secs(30),
+            frame: Duration::from_secs(10),
+            shutdown: Duration::from_secs(10),
+        }
+    }
+}
+
+enum Outgoing {
+    /// A packet ID followed by its data, not yet length-prefixed.
+    Packet(Vec<u8>),
+    /// Compress every packet after this one that's at least this long.
+    Compress(usize),
+    /// Encrypt everything after this with the given shared secret.
+    Encrypt([u8; 16]),
+    /// Flush whatever is queued and shut the socket down.
+    Close,
+}
+
+struct Shared {
+    state: Mutex<ConnectionState>,
+    closed: CancellationToken,
+    keep_alive: KeepAlive,
+}
+
+/// A cheap, cloneable way of talking to a connection from outside of the task
+/// reading from it. Packets are queued and written out by a separate task.
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    shared: Arc<Shared>,
+}
+
+impl ConnectionHandle {
+    pub fn state(&self) -> ConnectionState {
+        *self.shared.state.lock().unwrap()
+    }
+
+    pub fn keep_alive(&self) -> &KeepAlive {
+        &self.shared.keep_alive
+    }
+
+    /// Whether both handles are for the same connection.
+    pub fn is(&self, other: &ConnectionHandle) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    pub fn send_packet<T: Serialize + IdentifiedPacket>(&self, packet: T) -> Result<(), HandleError> {
+        let mut data = write_varint(T::ID);
+        data.extend(to_vec(&packet)?);
+
+        self.queue(Outgoing::Packet(data))
+    }
+
+    /// Sends a packet ID followed by its data, as is. For relaying packets
+    /// hubby doesn't need to understand.
+    pub fn send_raw(&self, data: Vec<u8>) -> Result<(), HandleError> {
+        self.queue(Outgoing::Packet(data))
+    }
+
+    /// Whether the connection has closed, or is closing.
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.is_cancelled()
+    }
+
+    /// Waits for the connection to close.
+    pub async fn closed(&self) {
+        self.shared.closed.cancelled().await
+    }
+
+    fn queue(&self, outgoing: Outgoing) -> Result<(), HandleError> {
+        self.outgoing.send(outgoing)
+            .map_err(|_| HandleError::Io(io::Error::new(ErrorKind::BrokenPipe, "connection closed")))
+    }
+
+    /// Queues the state-appropriate Disconnect packet (if any, see
+    /// [`Connection::disconnect`]) and closes the connection once it's sent.
+    pub fn disconnect(&self, reason: String) {
+        let result = match self.state() {
+            ConnectionState::Login => self.send_packet(LoginDisconnect { reason }),
+            ConnectionState::Play => self.send_packet(Disconnect { reason }),
+            ConnectionState::Handshaking | ConnectionState::Status => Ok(()),
+        };
+
+        if let Err(e) = result {
+            eprintln!("failed to send disconnect packet; err = {}", e);
+        }
+
+        self.close();
+    }
+
+    fn close(&self) {
+        // The writer might be gone already, in which case there's nothing to close.
+        let _ = self.outgoing.send(Outgoing::Close);
+        self.shared.closed.cancel();
+    }
 }
 
-impl Connection<'_> {
+/// Either half of whatever a connection is over: usually a TCP socket, but
+/// anything that moves bytes will do, like an in-memory pipe in tests.
+type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+pub struct Connection {
+    reader: BufReader<CipherReader<ReadHalf>>,
+    handle: ConnectionHandle,
+    writer: Option<JoinHandle<io::Result<()>>>,
+    keep_alive: Option<JoinHandle<()>>,
+    server: Arc<Server>,
+    /// When the current state has to be done by, if it has a deadline at all.
+    deadline: Option<Instant>,
+    peer: SocketAddr,
+    /// Whether the connection came in over `limits.max_connections`, and is
+    /// only kept around to tell the client so.
+    over_capacity: bool,
+    /// The threshold packets from the client are compressed at, once it's set.
+    compression: Option<usize>,
+    /// How the listener this came in on differs from the config.
+    options: ListenerOptions,
+    /// The hostname the client connected through, from its handshake.
+    virtual_host: String,
+    /// Who a trusted proxy says is connecting, until they log in.
+    forwarded: Option<ForwardedPlayer>,
+    /// The message ID of the login plugin request waiting on the proxy to
+    /// forward the player, in Velocity forwarding mode.
+    forwarding_request: Option<i32>,
+    /// Where an online mode login is at between Login Start and Encryption Response.
+    pending_login: Option<PendingLogin>,
+    /// The player on this connection, once they've joined.
+    player: Option<Arc<Player>>,
+    /// Where play packets go in proxy mode, instead of being handled here.
+    proxy: Option<ProxyHandle>,
+}
+
+impl Connection {
+    /// Sets up a connection to `peer` over `stream`, which is usually a
+    /// `TcpStream`. Nothing is read until [`Connection::listen`] is called.
+    pub fn new<S>(stream: S, peer: SocketAddr, server: Arc<Server>) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let (reader, writer): (ReadHalf, WriteHalf) = (Box::new(reader), Box::new(writer));
+        let (outgoing, queue) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(ConnectionState::Handshaking),
+            closed: CancellationToken::new(),
+            keep_alive: KeepAlive::default(),
+        });
+
+        let closed = shared.closed.clone();
+        let traffic = server.traffic.clone();
+        let writer = tokio::spawn(async move {
+            let result = write_packets(writer, queue, &traffic).await;
+            // Nobody's going to read what the client has to say anymore.
+            closed.cancel();
+            result
+        });
+
+        Connection {
+            reader: BufReader::new(CipherReader::new(reader)),
+            handle: ConnectionHandle { outgoing, shared },
+            writer: Some(writer),
+            keep_alive: None,
+            deadline: Some(Instant::now() + server.config().limits.timeouts.handshake),
+            peer,
+            over_capacity: false,
+            server,
+            compression: None,
+            options: ListenerOptions::default(),
+            virtual_host: String::new(),
+            forwarded: None,
+            forwarding_request: None,
+            pending_login: None,
+            player: None,
+            proxy: None,
+        }
+    }
+
+    /// Applies the options of the listener the connection came in on.
+    pub fn with_options(mut self, options: ListenerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Marks the connection as one too many, to be turned away once the
+    /// client says what it's here for.
+    pub fn over_capacity(mut self) -> Self {
+        self.over_capacity = true;
+        self
+    }
+
+    /// How players on this connection are forwarded by a proxy, if at all.
+    pub fn forwarding(&self, config: &Config) -> Forwarding {
+        self.options.forwarding.unwrap_or(config.forwarding.mode)
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Replaces the peer's address with the one a trusted proxy says is the
+    /// client's, which is what rate limits apply to from then on.
+    pub fn set_peer(&mut self, peer: SocketAddr) {
+        self.peer = peer;
+    }
+
+    pub fn forwarded(&mut self) -> &mut Option<ForwardedPlayer> {
+        &mut self.forwarded
+    }
+
+    pub fn forwarding_request(&mut self) -> &mut Option<i32> {
+        &mut self.forwarding_request
+    }
+
+    pub fn virtual_host(&self) -> &str {
+        &self.virtual_host
+    }
+
+    pub fn set_virtual_host(&mut self, host: String) {
+        self.virtual_host = host;
+    }
+
+    pub fn pending_login(&mut self) -> &mut Option<PendingLogin> {
+        &mut self.pending_login
+    }
+
+    /// Marks this connection as `player`'s, so they're taken off the player
+    /// list once it closes.
+    pub fn set_player(&mut self, player: Arc<Player>) {
+        self.player = Some(player);
+    }
+
+    pub fn player(&self) -> Option<&Arc<Player>> {
+        self.player.as_ref()
+    }
+
+    /// Hands everything the client says from now on, other than keep-alives,
+    /// over to `proxy`.
+    pub fn set_proxy(&mut self, proxy: ProxyHandle) {
+        self.proxy = Some(proxy);
+    }
+
+    /// Takes one of the peer's connection tokens, and returns whether it had
+    /// none left. Only called once per connection, when the real peer is
+    /// known, so proxies in front of the hub don't use up their own bucket.
+    pub fn check_throttled(&self) -> bool {
+        !self.server.limits.connections.check(self.peer.ip())
+    }
+
+    pub fn is_over_capacity(&self) -> bool {
+        self.over_capacity
+    }
+
+    pub fn handle(&self) -> &ConnectionHandle {
+        &self.handle
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.handle.state()
+    }
+
     pub async fn listen(mut self) {
-        loop {
-            let len = match read_varint_tcp(self.socket).await {
-                Ok(x) => x,
-                Err(VarIntError::Io(e)) => {
-                    if e.kind() == ErrorKind::UnexpectedEof {
-                        println!("Disconnected.");
-                    } else {
-                        eprintln!("failed to read packet length from socket; err = {:?}", e);
-                    }
-                    return;
+        let closed = self.handle.shared.closed.clone();
+        let shutdown = self.server.shutdown.clone();
+
+        let trusted = || self.server.config().network.proxy_protocol.contains(&self.peer.ip().to_canonical());
+        if self.options.proxy_protocol.unwrap_or_else(trusted) {
+            if let Err(e) = self.read_proxy_header().await {
+                self.close(e).await;
+            }
+        }
+
+        while !closed.is_cancelled() {
+            let packet = tokio::select! {
+                _ = closed.cancelled() => break,
+                _ = shutdown.cancelled() => {
+                    self.handle.disconnect(packets::text_component(&self.server.config().messages.shutting_down));
+                    break;
                 },
-                Err(e) => {
-                    eprintln!("failed to read packet length from socket; err = {:?}", e);
-                    return;
-                }
+                packet = self.read_packet() => packet,
+            };
+
+            let handle_result = match packet {
+                Ok((id, data)) => {
+                    self.server.traffic.received.fetch_add(1, Ordering::Relaxed);
+                    self.handle_packet(id, &data).await
+                },
+                Err(e) => Err(e),
             };
 
-            let mut vec = vec![0_u8; len as usize];
-            match self.socket.read_exact(&mut vec).await {
+            match handle_result {
                 Ok(_) => {},
-                Err(e) => {
-                    eprintln!("failed to read packet; err = {:?}", e);
-                    return;
-                }
+                // Play has plenty of packets we can safely ignore. Every other state is
+                // a fixed exchange, so anything we don't understand there is fatal.
+                Err(HandleError::Unimplemented(id)) if self.state() == ConnectionState::Play => {
+                    eprintln!("packet ID {} unimplemented", id)
+                },
+                Err(e) => self.close(e).await,
             };
+        }
+
+        if let Some(keep_alive) = self.keep_alive.take() {
+            keep_alive.abort();
+        }
+
+        if let Some(player) = self.player.take() {
+            self.server.remove_player(player.uuid, &self.handle);
+        }
+
+        self.handle.close();
+        if let Err(e) = self.finish_writing().await {
+            eprintln!("failed to close connection cleanly; err = {}", e);
+        }
+    }
+
+    /// Takes the client's address from the PROXY protocol header in front of
+    /// everything else.
+    async fn read_proxy_header(&mut self) -> Result<(), HandleError> {
+        let header = proxy_protocol::read_header(&mut self.reader);
+        let client = match self.deadline {
+            Some(deadline) => time::timeout_at(deadline, header).await
+                .map_err(|_| HandleError::Timeout("reading PROXY header"))??,
+            None => header.await?,
+        };
+
+        if let Some(client) = client {
+            println!("{} is forwarding {}", self.peer, client);
+            self.set_peer(client);
+        }
+
+        Ok(())
+    }
 
-            let mut buf = &mut vec[..];
+    async fn read_packet(&mut self) -> Result<(i32, Vec<u8>), HandleError> {
+        // Waiting for the next packet is fine for as long as the state allows,
+        // but once it starts arriving it has to arrive in one go.
+        let state_name = self.state_name();
+        let waiting = self.reader.fill_buf();
+        let available = match self.deadline {
+            Some(deadline) => time::timeout_at(deadline, waiting).await
+                .map_err(|_| HandleError::Timeout(state_name))??,
+            None => waiting.await?,
+        };
 
-            let (id, id_len) = match read_varint(buf) {
-                Ok(x) => x,
-                Err(e) => {
-                    eprintln!("failed to read packet ID from socket; err = {:?}", e);
-                    return;
+        if available.is_empty() {
+            return Err(HandleError::Io(ErrorKind::UnexpectedEof.into()));
+        }
+
+        let frame_deadline = Instant::now() + self.server.config().limits.timeouts.frame;
+        let frame_deadline = self.deadline.map_or(frame_deadline, |x| x.min(frame_deadline));
+
+        time::timeout_at(frame_deadline, self.read_frame()).await
+            .map_err(|_| HandleError::Timeout("reading packet"))?
+    }
+
+    async fn read_frame(&mut self) -> Result<(i32, Vec<u8>), HandleError> {
+        let len = read_varint_async(&mut self.reader).await?;
+
+        if len <= 0 || len > MAX_PACKET_LENGTH {
+            return Err(HandleError::BadPacket(format!("invalid packet length {}", len)));
+        }
+
+        let mut vec = vec![0_u8; len as usize];
+        self.reader.read_exact(&mut vec).await?;
+
+        if let Some(threshold) = self.compression {
+            vec = codec::decompress(&vec, threshold)?;
+        }
+
+        let (id, id_len) = read_varint(&vec)
+            .map_err(|e| HandleError::BadPacket(format!("failed to read packet ID; err = {}", e)))?;
+
+        vec.drain(..id_len);
+
+        Ok((id, vec))
+    }
+
+    async fn handle_packet(&mut self, id: i32, buf: &[u8]) -> Result<(), HandleError> {
+        let server = self.server.clone();
+
+        if let Some(handler) = server.handler(self.state(), id) {
+            return handler(server, self.handle.clone(), buf.to_vec()).await;
+        }
+
+        if let Some(proxy) = &self.proxy {
+            // The hub's keep-alives are answered to the hub, the backend's
+            // are answered by the proxy.
+            if id != ServerboundKeepAlive::ID {
+                let mut data = write_varint(id);
+                data.extend(buf);
+                // Stop reading from the player while the backend catches up,
+                // unless they're disconnected in the meantime.
+                tokio::select! {
+                    _ = self.handle.closed() => {},
+                    _ = proxy.forward(data) => {},
                 }
-            };
+                return Ok(());
+            }
+        }
 
-            buf = &mut buf[id_len..];
+        match self.state() {
+            ConnectionState::Handshaking => packets::handshaking::handle(&server, self, id, buf).await,
+            ConnectionState::Status => packets::status::handle(&server, self, id, buf).await,
+            ConnectionState::Login => packets::login::handle(&server, self, id, buf).await,
+            ConnectionState::Play => packets::play::handle(&server, self, id, buf).await,
+        }
+    }
 
-            println!("Received packet ID {} with content {:?}", id, buf);
+    /// Reports `err`, and tells the client why it's being dropped if the
+    /// current state has a way of doing so.
+    async fn close(&mut self, err: HandleError) {
+        if let HandleError::Io(e) = &err {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                println!("Disconnected.");
+                self.handle.close();
+                return;
+            }
+        }
 
-            let handle_result = match self.state {
-                ConnectionState::Handshaking => packets::handshaking::handle(&mut self, id, buf).await,
-                ConnectionState::Status => packets::status::handle(&mut self, id, buf).await,
-                _ => panic!("idk")
-            };
+        eprintln!("closing connection; err = {}", err);
 
-            match handle_result {
-                Ok(_) => {},
-                Err(e) => match e {
-                    HandleError::SerdeMCJE(e) => {
-                        eprintln!("failed to parse packet; err = {:#?}", e);
-                        return;
-                    },
-                    HandleError::Unimplemented(id) => eprintln!("packet ID {} unimplemented", id),
-                    HandleError::BadPacket(e) => {
-                        eprintln!("invalid packet received; {}", e);
-                        return;
-                    }
+        match err.reason() {
+            Some(reason) => {
+                if let Err(e) = self.disconnect(reason).await {
+                    eprintln!("failed to disconnect cleanly; err = {}", e);
                 }
-            };
+            },
+            None => self.handle.close(),
+        }
+    }
+
+    /// Kicks the client, showing it `reason` (a JSON text component, see
+    /// [`packets::text_component`]) if the current state allows for it.
+    /// Handshaking and Status have no such packet, so those are just closed.
+    ///
+    /// Once this returns, the connection stops listening for packets.
+    pub async fn disconnect(&mut self, reason: String) -> Result<(), HandleError> {
+        self.handle.disconnect(reason);
+        self.finish_writing().await?;
+
+        Ok(())
+    }
+
+    /// Waits for the writer task to flush everything and shut the socket down.
+    async fn finish_writing(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            Some(writer) => writer.await?,
+            None => Ok(()),
+        }
+    }
+
+    fn state_name(&self) -> &'static str {
+        match self.state() {
+            ConnectionState::Handshaking => "handshake",
+            ConnectionState::Status => "status",
+            ConnectionState::Login => "login",
+            ConnectionState::Play => "play",
         }
     }
 
     pub fn switch_state(&mut self, new_state: ConnectionState) {
-        self.state = new_state;
+        *self.handle.shared.state.lock().unwrap() = new_state;
+
+        let timeouts = self.server.config().limits.timeouts;
+        self.deadline = match new_state {
+            ConnectionState::Handshaking => Some(Instant::now() + timeouts.handshake),
+            ConnectionState::Status => Some(Instant::now() + timeouts.status),
+            ConnectionState::Login => Some(Instant::now() + timeouts.login),
+            ConnectionState::Play => None,
+        };
+
+        if new_state == ConnectionState::Play && self.keep_alive.is_none() {
+            self.keep_alive = Some(tokio::spawn(keep_alive::run(self.handle.clone())));
+        }
+    }
+
+    pub fn send_packet<T: Serialize + IdentifiedPacket>(&mut self, packet: T) -> Result<(), HandleError> {
+        self.handle.send_packet(packet)
     }
 
-    pub async fn send_packet<T: Serialize + IdentifiedPacket>(&mut self, packet: T) {
-        let id = write_varint(T::ID);
-        let pak = to_vec(&packet).unwrap();
+    /// Switches both directions over to the compressed packet format. Call
+    /// this right after queueing Set Compression, which is itself sent as is.
+    pub fn enable_compression(&mut self, threshold: usize) -> Result<(), HandleError> {
+        self.compression = Some(threshold);
+        self.handle.queue(Outgoing::Compress(threshold))
+    }
+
+    /// Encrypts everything from here on, in both directions.
+    pub fn enable_encryption(&mut self, secret: &[u8; 16]) -> Result<(), HandleError> {
+        // Anything the client sent after its Encryption Response that we've
+        // buffered already came in encrypted.
+        let read_ahead = self.reader.buffer().to_vec();
+        self.reader.consume(read_ahead.len());
+        self.reader.get_mut().enable(secret, &read_ahead);
 
-        self.socket.write_all(&write_varint((id.len() + pak.len()) as i32)).await.unwrap();
-        self.socket.write_all(&id).await.unwrap();
-        self.socket.write_all(&pak).await.unwrap();
+        self.handle.queue(Outgoing::Encrypt(*secret))
     }
-}
\ No newline at end of file
+}
+
+async fn write_packets(socket: WriteHalf, mut queue: mpsc::UnboundedReceiver<Outgoing>, traffic: &Traffic) -> io::Result<()> {
+    let mut socket = BufWriter::new(socket);
+    let mut compression = None;
+    let mut cipher = None;
+
+    while let Some(outgoing) = queue.recv().await {
+        match outgoing {
+            Outgoing::Packet(data) => {
+                let body = match compression {
+                    Some(threshold) => codec::compress(&data, threshold)?,
+                    None => data,
+                };
+
+                let mut frame = write_varint(body.len() as i32);
+                frame.extend(body);
+
+                if let Some(cipher) = &mut cipher {
+                    codec::encrypt(cipher, &mut frame);
+                }
+
+                socket.write_all(&frame).await?;
+                traffic.sent.fetch_add(1, Ordering::Relaxed);
+            },
+            Outgoing::Compress(threshold) => compression = Some(threshold),
+            Outgoing::Encrypt(secret) => cipher = Some(codec::encryptor(&secret)),
+            Outgoing::Close => break,
+        }
+
+        // Batch up whatever was queued at once instead of flushing every packet.
+        if queue.is_empty() {
+            socket.flush().await?;
+        }
+    }
+
+    socket.flush().await?;
+    socket.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde::Serialize;
+    use serde_mcje::{from_slice, to_vec, types::VarInt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    use crate::{client::Client, codec, config::Config, packets::login::offline_uuid, server::Server, varint::*};
+
+    use super::Connection;
+
+    /// Starts a connection to `server` over an in-memory pipe, returning the
+    /// client's end of it.
+    fn connect(server: &Arc<Server>) -> DuplexStream {
+        let (client, socket) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(Connection::new(socket, "127.0.0.1:25565".parse().unwrap(), server.clone()).listen());
+
+        client
+    }
+
+    async fn send<T: Serialize>(client: &mut DuplexStream, id: i32, packet: T, compression: Option<usize>) {
+        let mut data = write_varint(id);
+        data.extend(to_vec(&packet).unwrap());
+        if let Some(threshold) = compression {
+            data = codec::compress(&data, threshold).unwrap();
+        }
+
+        let mut frame = write_varint(data.len() as i32);
+        frame.extend(data);
+        client.write_all(&frame).await.unwrap();
+    }
+
+    async fn recv(client: &mut DuplexStream, compression: Option<usize>) -> (i32, Vec<u8>) {
+        let len = read_varint_async(client).await.unwrap();
+        let mut data = vec![0; len as usize];
+        client.read_exact(&mut data).await.unwrap();
+        if let Some(threshold) = compression {
+            data = codec::decompress(&data, threshold).unwrap();
+        }
+
+        let (id, id_len) = read_varint(&data).unwrap();
+        (id, data[id_len..].to_vec())
+    }
+
+    async fn assert_closed(client: &mut DuplexStream) {
+        assert_eq!(client.read(&mut [0; 1]).await.unwrap(), 0);
+    }
+
+    fn server() -> Arc<Server> {
+        let mut config = Config::default();
+        config.status.motd = "In memory".to_string();
+        config.world.view_distance = 2;
+
+        Server::builder().config(config).build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn status() {
+        let server = server();
+        let mut client = connect(&server);
+
+        send(&mut client, 0x00, (VarInt(759), "localhost", 25565_u16, VarInt(1)), None).await;
+        // serde_mcje has no unit type, but an empty array is just as empty.
+        send(&mut client, 0x00, [0_u8; 0], None).await;
+
+        let (id, data) = recv(&mut client, None).await;
+        assert_eq!(id, 0x00);
+        let status: serde_json::Value = serde_json::from_str(&from_slice::<String>(&data).unwrap()).unwrap();
+        assert_eq!(status["description"]["text"], "In memory");
+        assert_eq!(status["players"]["online"], 0);
+
+        send(&mut client, 0x01, 42_i64, None).await;
+        assert_eq!(recv(&mut client, None).await, (0x01, 42_i64.to_be_bytes().to_vec()));
+    }
+
+    #[tokio::test]
+    async fn login_and_play() {
+        let server = server();
+        let mut client = connect(&server);
+
+        send(&mut client, 0x00, (VarInt(759), "localhost", 25565_u16, VarInt(2)), None).await;
+        send(&mut client, 0x00, ("Steve", false), None).await;
+
+        let (id, data) = recv(&mut client, None).await;
+        assert_eq!(id, 0x03);
+        let threshold = Some(from_slice::<VarInt>(&data).unwrap().0 as usize);
+
+        let (id, data) = recv(&mut client, threshold).await;
+        assert_eq!(id, 0x02);
+        let (uuid, name, properties): (u128, String, VarInt) = from_slice(&data).unwrap();
+        assert_eq!(uuid, offline_uuid("Steve").as_u128());
+        assert_eq!((name.as_str(), properties.0), ("Steve", 0));
+
+        assert_eq!(recv(&mut client, threshold).await.0, 0x23);
+        let mut chunks = 0;
+        loop {
+            match recv(&mut client, threshold).await.0 {
+                0x1F => chunks += 1,
+                0x36 => break,
+                _ => {},
+            }
+        }
+        assert_eq!(chunks, 25);
+        assert_eq!(server.players.len(), 1);
+        // Then they're shown themselves in the tab list.
+        assert_eq!(recv(&mut client, threshold).await.0, 0x34);
+
+        // Nothing asked for this one, so it gets the client kicked.
+        send(&mut client, 0x11, 7_i64, threshold).await;
+        assert_eq!(recv(&mut client, threshold).await.0, 0x17);
+        assert_closed(&mut client).await;
+        assert_eq!(server.players.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn proxy_protocol() {
+        let mut config = Config::default();
+        config.network.proxy_protocol = vec!["127.0.0.1".parse().unwrap()];
+        config.limits.connections.burst = 1;
+        let server = Server::builder().config(config).build().unwrap();
+
+        let status = |header: &'static [u8]| {
+            let server = server.clone();
+            async move {
+                let mut stream = connect(&server);
+                stream.write_all(header).await.unwrap();
+                Client::new(stream, "localhost", 25565).status().await
+            }
+        };
+
+        // Rate limits go by the address in the header, not the balancer's.
+        status(b"PROXY TCP4 203.0.113.1 10.0.0.1 50000 25565\r\n").await.unwrap();
+        status(b"PROXY TCP4 203.0.113.2 10.0.0.1 50000 25565\r\n").await.unwrap();
+        assert!(status(b"PROXY TCP4 203.0.113.1 10.0.0.1 50001 25565\r\n").await.is_err());
+
+        // Trusted balancers have to send one.
+        assert!(status(b"").await.is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{proxy::TransferError, reload, server::Server};
+
+/// Runs commands typed into the server's standard input.
+pub async fn run(server: Arc<Server>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {},
+            ["reload"] => reload::reload(&server),
+            ["send", name, backend] => send(&server, name, backend),
+            ["stop"] => server.shutdown(),
+            ["help"] => println!("commands: reload, send <player> <backend>, stop, help"),
+            _ => println!("unknown command {:?}, try \"help\"", line.trim()),
+        }
+    }
+}
+
+/// Moves a player to another backend, in proxy mode.
+fn send(server: &Server, name: &str, backend: &str) {
+    let result = match server.players.by_name(name) {
+        Some(player) => server.send_to_backend(player.uuid, backend),
+        None => Err(TransferError::NotOnline),
+    };
+
+    match result {
+        Ok(()) => println!("sending {} to {}", name, backend),
+        Err(e) => println!("can't send {} to {}: {}", name, backend, e),
+    }
+}
@@ -0,0 +1,85 @@
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// How many events a slow listener can fall behind by before it starts
+/// missing some.
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A player made it through login and into the world.
+    PlayerJoined { uuid: Uuid, name: String },
+    PlayerLeft { uuid: Uuid, name: String },
+    ConfigReloaded,
+    /// The last event. Listeners should save anything worth keeping, since
+    /// the server only waits so long for them to finish.
+    ShuttingDown,
+}
+
+/// Lets any part of the server find out about things happening elsewhere
+/// without the two knowing about each other.
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus { sender: broadcast::channel(CAPACITY).0 }
+    }
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: Event) {
+        // Nobody listening is fine.
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Waits for the next event, skipping over any that were missed by falling
+/// behind. Returns `None` once the bus is gone.
+pub async fn next(events: &mut broadcast::Receiver<Event>) -> Option<Event> {
+    loop {
+        match events.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(missed)) => eprintln!("event listener fell behind; missed {} events", missed),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Prints players coming and going to the console.
+pub async fn log(mut events: broadcast::Receiver<Event>) {
+    while let Some(event) = next(&mut events).await {
+        match event {
+            Event::PlayerJoined { uuid, name } => println!("{} ({}) joined", name, uuid),
+            Event::PlayerLeft { name, .. } => println!("{} left", name),
+            Event::ConfigReloaded => {},
+            Event::ShuttingDown => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Event, EventBus};
+
+    #[tokio::test]
+    async fn listeners_get_events() {
+        let events = EventBus::default();
+        events.emit(Event::ConfigReloaded);
+
+        let mut first = events.subscribe();
+        let mut second = events.subscribe();
+        events.emit(Event::PlayerJoined { uuid: Uuid::nil(), name: "Notch".to_string() });
+
+        for listener in [&mut first, &mut second] {
+            assert_eq!(listener.recv().await.unwrap(), Event::PlayerJoined { uuid: Uuid::nil(), name: "Notch".to_string() });
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Velocity's "modern" forwarding, where the proxy answers a login plugin
+//! request with the player's identity, signed with a secret it shares with
+//! the servers behind it.
+
+use std::net::IpAddr;
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_mcje::{from_slice, to_vec, types::{PrefixedArray, VarInt}};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::packets::login::Property;
+
+/// The channel of the login plugin request Velocity answers.
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// The version of the forwarding format asked for. Later ones add the
+/// player's chat signing key, which hubby has no use for.
+pub const VELOCITY_VERSION: u8 = 1;
+
+const SIGNATURE_LENGTH: usize = 32;
+
+/// A player's identity as Velocity forwards it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VelocityPlayer {
+    pub ip: IpAddr,
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<Property>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    version: VarInt,
+    address: String,
+    uuid: u128,
+    name: String,
+    properties: PrefixedArray<Property>,
+}
+
+impl VelocityPlayer {
+    /// Checks the signature on the data of a login plugin response, then
+    /// reads the player out of it.
+    pub fn read(secret: &[u8], data: &[u8]) -> Result<VelocityPlayer, String> {
+        if data.len() < SIGNATURE_LENGTH {
+            return Err("too short to be signed".to_string());
+        }
+
+        let (signature, payload) = data.split_at(SIGNATURE_LENGTH);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).map_err(|e| e.to_string())?;
+        mac.update(payload);
+        mac.verify_slice(signature).map_err(|_| "bad signature, check that the forwarding secret matches the proxy's".to_string())?;
+
+        let payload: Payload = from_slice(payload).map_err(|e| format!("malformed player info; err = {}", e))?;
+        if payload.version.0 != VELOCITY_VERSION as i32 {
+            return Err(format!("unsupported forwarding version {}", payload.version));
+        }
+
+        Ok(VelocityPlayer {
+            ip: payload.address.parse().map_err(|_| format!("invalid address {:?}", payload.address))?,
+            uuid: Uuid::from_u128(payload.uuid),
+            name: payload.name,
+            properties: payload.properties.0,
+        })
+    }
+
+    /// Signs the player with `secret`, as a proxy would.
+    pub fn write(&self, secret: &[u8]) -> Vec<u8> {
+        let payload = to_vec(&Payload {
+            version: VarInt(VELOCITY_VERSION as i32),
+            address: self.ip.to_string(),
+            uuid: self.uuid.as_u128(),
+            name: self.name.clone(),
+            properties: PrefixedArray(self.properties.clone()),
+        }).expect("player info always serializes");
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+        mac.update(&payload);
+
+        let mut data = mac.finalize().into_bytes().to_vec();
+        data.extend(payload);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::{client::{Client, ClientError}, config::{Config, Forwarding}, packets::login::Property, server::Server};
+
+    use super::VelocityPlayer;
+
+    fn player() -> VelocityPlayer {
+        VelocityPlayer {
+            ip: "203.0.113.7".parse().unwrap(),
+            uuid: Uuid::from_u128(0xf84c6a790a4e45e0879bcd49ebd4c4e2),
+            name: "Alex".to_string(),
+            properties: vec![Property { name: "textures".to_string(), value: "abc".to_string(), signature: Some("sig".to_string()) }],
+        }
+    }
+
+    #[test]
+    fn velocity_roundtrip() {
+        let player = player();
+        let mut data = player.write(b"secret");
+        assert_eq!(VelocityPlayer::read(b"secret", &data), Ok(player));
+        assert!(VelocityPlayer::read(b"other", &data).is_err());
+
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(VelocityPlayer::read(b"secret", &data).is_err());
+        assert!(VelocityPlayer::read(b"secret", &[]).is_err());
+    }
+
+    #[tokio::test]
+    async fn velocity_login() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        config.forwarding.mode = Forwarding::Velocity;
+        config.forwarding.secret = "secret".to_string();
+        let server = Server::builder().config(config).build().unwrap();
+
+        let mut client = Client::in_memory(&server, "10.0.0.1:50000".parse().unwrap());
+        client.forward_velocity(b"secret", player());
+        // The name comes from the proxy, not Login Start.
+        let profile = client.login_offline("Steve").await.unwrap();
+        assert_eq!((profile.uuid, profile.username.as_str()), (player().uuid.as_u128(), "Alex"));
+        assert_eq!(profile.properties.0, player().properties);
+
+        let mut client = Client::in_memory(&server, "10.0.0.1:50000".parse().unwrap());
+        client.forward_velocity(b"wrong", player());
+        assert!(matches!(client.login_offline("Alex").await, Err(ClientError::Disconnected(_))));
+
+        let mut client = Client::in_memory(&server, "10.0.0.1:50000".parse().unwrap());
+        assert!(matches!(client.login_offline("Alex").await, Err(ClientError::Disconnected(_))));
+        assert_eq!(server.players.len(), 1);
+    }
+}
@@ -0,0 +1,76 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::{sync::Notify, time::{self, Instant}};
+
+use crate::{connection::ConnectionHandle, packets::{HandleError, play::ClientboundKeepAlive}};
+
+/// How often a Keep Alive is sent to clients in the Play state.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a client gets to answer before it's considered dead. Vanilla
+/// clients give up on the server after the same amount of silence.
+pub const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keep-alive bookkeeping for one connection: the ID we're waiting to hear
+/// back, and the round-trip time of the last one that came back.
+#[derive(Default)]
+pub struct KeepAlive {
+    pending: Mutex<Option<(i64, Instant)>>,
+    latency: Mutex<Option<Duration>>,
+    acknowledged: Notify,
+}
+
+impl KeepAlive {
+    /// The round-trip time of the last answered Keep Alive, if there was one.
+    pub fn latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap()
+    }
+
+    fn start(&self, id: i64) {
+        *self.pending.lock().unwrap() = Some((id, Instant::now()));
+    }
+
+    /// Records the client's answer to the pending Keep Alive. Answering with
+    /// the wrong ID, or when nothing was asked, is a protocol violation.
+    pub fn acknowledge(&self, id: i64) -> Result<(), HandleError> {
+        let mut pending = self.pending.lock().unwrap();
+
+        match *pending {
+            Some((expected, sent)) if expected == id => {
+                *pending = None;
+                *self.latency.lock().unwrap() = Some(sent.elapsed());
+                self.acknowledged.notify_one();
+
+                Ok(())
+            },
+            _ => Err(HandleError::ProtocolViolation(format!("unexpected keep-alive ID {}", id))),
+        }
+    }
+}
+
+/// Sends a Keep Alive every [`KEEP_ALIVE_INTERVAL`] until the connection goes
+/// away, kicking the client if one goes unanswered for [`KEEP_ALIVE_TIMEOUT`].
+pub async fn run(conn: ConnectionHandle) {
+    let mut interval = time::interval_at(Instant::now() + KEEP_ALIVE_INTERVAL, KEEP_ALIVE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let id = rand::random();
+        conn.keep_alive().start(id);
+
+        if conn.send_packet(ClientboundKeepAlive { id }).is_err() {
+            return;
+        }
+
+        if time::timeout(KEEP_ALIVE_TIMEOUT, conn.keep_alive().acknowledged.notified()).await.is_err() {
+            let err = HandleError::Timeout("keep-alive");
+
+            eprintln!("closing connection; err = {}", err);
+            if let Some(reason) = err.reason() {
+                conn.disconnect(reason);
+            }
+
+            return;
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! A Minecraft hub server. Run it as is with the `hubby` binary, or embed it:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use hubby::{ConnectionState, Event, Server};
+//!
+//! let server = Server::builder()
+//!     .config_file("hubby.toml")
+//!     .bind("0.0.0.0:25565".parse()?)
+//!     .handler(ConnectionState::Play, 0x0C, |_server, _conn, data| async move {
+//!         println!("plugin message: {:?}", data);
+//!         Ok(())
+//!     })
+//!     .listener(|event| {
+//!         if let Event::PlayerJoined { name, .. } = event {
+//!             println!("welcome, {}", name);
+//!         }
+//!     })
+//!     .build()?;
+//!
+//! server.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod varint;
+pub mod auth;
+pub mod chat;
+pub mod client;
+pub mod codec;
+pub mod config;
+pub mod connection;
+mod console;
+pub mod events;
+pub mod forwarding;
+pub mod keep_alive;
+mod listener;
+pub mod monitor;
+pub mod nbt;
+pub mod packets;
+pub mod player;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod rate_limit;
+mod reload;
+pub mod server;
+pub mod tab_list;
+pub mod world;
+
+pub use config::Config;
+pub use connection::{Connection, ConnectionHandle, ConnectionState};
+pub use events::Event;
+pub use packets::HandleError;
+pub use server::{Server, ServerBuilder};
@@ -0,0 +1,80 @@
+use std::{io, net::{Ipv4Addr, SocketAddr}};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::config::ListenAddress;
+
+/// How many connections can wait to be accepted.
+const BACKLOG: i32 = 1024;
+
+/// Where Unix socket connections appear to come from, since there's no
+/// address to go by.
+pub const UNIX_PEER: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// A socket connections are accepted from.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Binds to `address`. IPv6 addresses take IPv4 connections too, unless
+    /// `ipv6_only` is set.
+    pub fn bind(address: &ListenAddress, ipv6_only: bool) -> io::Result<Listener> {
+        match address {
+            ListenAddress::Tcp(addr) => {
+                let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::TCP))?;
+                if addr.is_ipv6() {
+                    socket.set_only_v6(ipv6_only)?;
+                }
+                socket.set_reuse_address(true)?;
+                socket.set_nonblocking(true)?;
+                socket.bind(&(*addr).into())?;
+                socket.listen(BACKLOG)?;
+
+                Ok(Listener::Tcp(TcpListener::from_std(socket.into())?))
+            },
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+
+                // A socket nobody's listening on is left over from last time.
+                if std::fs::symlink_metadata(path).is_ok_and(|x| x.file_type().is_socket()) {
+                    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                        return Err(io::Error::new(io::ErrorKind::AddrInUse, "something is already listening there"));
+                    }
+                    std::fs::remove_file(path)?;
+                }
+
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            },
+            #[cfg(not(unix))]
+            ListenAddress::Unix(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Unix sockets aren't supported on this platform")),
+        }
+    }
+
+    /// Where it's listening, as it'd be written in the config.
+    pub fn local_addr(&self) -> io::Result<ListenAddress> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(ListenAddress::Tcp),
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(ListenAddress::Unix(listener.local_addr()?.as_pathname().unwrap_or_else(|| "".as_ref()).to_path_buf())),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    /// Unix sockets stay behind as files unless they're removed.
+    fn drop(&mut self) {
+        if let Listener::Unix(listener) = self {
+            if let Some(path) = listener.local_addr().ok().and_then(|x| x.as_pathname().map(|x| x.to_path_buf())) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
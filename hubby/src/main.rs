@@ -1,24 +1,22 @@
-mod varint;
-mod connection;
-mod packets;
+use std::{path::PathBuf, process};
 
-use connection::Connection;
-use tokio::net::TcpListener;
+use hubby::{config, Server};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let listener = TcpListener::bind("0.0.0.0:2346").await?;
+    let config_path = std::env::args_os().nth(1).map_or(PathBuf::from(config::DEFAULT_PATH), PathBuf::from);
 
-    loop {
-        let (mut socket, _) = listener.accept().await?;
+    let server = match Server::builder().config_file(config_path).console(true).build() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
 
-        tokio::spawn(async move {
-            let conn = Connection {
-                socket: &mut socket,
-                state: connection::ConnectionState::Handshaking,
-            };
+    server.run().await?;
 
-            conn.listen().await;
-        });
-    }
-}
\ No newline at end of file
+    // The console's read of standard input can't be interrupted, and the
+    // runtime would wait on it forever.
+    process::exit(0)
+}
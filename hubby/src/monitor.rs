@@ -0,0 +1,137 @@
+use std::{sync::{Arc, RwLock}, time::Duration};
+
+use tokio::{task::JoinSet, time};
+
+use crate::{client::{Client, ServerStatus}, config::BackendsConfig, server::Server};
+
+/// Keeps track of whether the game servers behind the hub are up, and how
+/// full they are, by pinging them every so often.
+#[derive(Default)]
+pub struct Monitor {
+    /// In config order, as of the last poll. `None` for servers that are down.
+    statuses: RwLock<Vec<(String, Option<ServerStatus>)>>,
+}
+
+impl Monitor {
+    /// The last known status of the backend called `name`, if it's up.
+    pub fn status(&self, name: &str) -> Option<ServerStatus> {
+        self.statuses.read().unwrap().iter()
+            .find(|(x, _)| x == name)
+            .and_then(|(_, status)| status.clone())
+    }
+
+    /// Every configured backend, with its status if it's up.
+    pub fn statuses(&self) -> Vec<(String, Option<ServerStatus>)> {
+        self.statuses.read().unwrap().clone()
+    }
+
+    /// How many players are on all the backends that are up.
+    pub fn online_players(&self) -> u32 {
+        self.statuses.read().unwrap().iter()
+            .filter_map(|(_, status)| status.as_ref())
+            .fold(0, |total, status| total.saturating_add(status.online))
+    }
+
+    /// Asks every backend in `config` for its status at once, and waits for
+    /// them all to answer or time out.
+    pub async fn poll(&self, config: &BackendsConfig) {
+        let mut queries = JoinSet::new();
+        for (i, backend) in config.servers.iter().enumerate() {
+            let (address, timeout) = (backend.address.clone(), config.timeout);
+            queries.spawn(async move {
+                let status = match time::timeout(timeout, Client::query(&address)).await {
+                    Ok(Ok(status)) => Ok(status),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(_) => Err(format!("no answer within {:?}", timeout)),
+                };
+
+                (i, status)
+            });
+        }
+
+        let mut results = vec![None; config.servers.len()];
+        while let Some(result) = queries.join_next().await {
+            if let Ok((i, status)) = result {
+                results[i] = Some(status);
+            }
+        }
+
+        let mut statuses = self.statuses.write().unwrap();
+        let new = config.servers.iter().zip(results).map(|(backend, result)| {
+            // Whether it was up last time, if it was polled at all.
+            let was_up = statuses.iter().find(|(name, _)| *name == backend.name).map(|(_, status)| status.is_some());
+            match result {
+                Some(Ok(status)) => {
+                    if was_up != Some(true) {
+                        println!("backend {} is up ({}/{} players)", backend.name, status.online, status.max);
+                    }
+                    (backend.name.clone(), Some(status))
+                },
+                Some(Err(e)) => {
+                    if was_up != Some(false) {
+                        eprintln!("backend {} is down; err = {}", backend.name, e);
+                    }
+                    (backend.name.clone(), None)
+                },
+                None => (backend.name.clone(), None),
+            }
+        }).collect();
+
+        *statuses = new;
+    }
+}
+
+/// Polls the backends for as long as the server runs, picking up changes to
+/// the list (and how often to poll it) on reload.
+pub async fn run(server: Arc<Server>) {
+    loop {
+        let config = server.config();
+        server.monitor.poll(&config.backends).await;
+
+        let interval = match config.backends.servers.is_empty() {
+            // Nothing to poll, but check back in case some get configured.
+            true => Duration::from_secs(1),
+            false => config.backends.poll_interval,
+        };
+        time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use crate::{config::{BackendConfig, BackendsConfig, Config}, server::Server};
+
+    use super::Monitor;
+
+    #[tokio::test]
+    async fn polls_backends() {
+        let mut config = Config::default();
+        config.status.motd = "Backend".to_string();
+        config.status.max_players = 50;
+        let backend = Server::builder().config(config).build().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(backend.accept(listener));
+
+        let config = BackendsConfig {
+            servers: vec![
+                BackendConfig { name: "lobby".to_string(), address },
+                BackendConfig { name: "gone".to_string(), address: "127.0.0.1:1".to_string() },
+            ],
+            ..BackendsConfig::default()
+        };
+
+        let monitor = Monitor::default();
+        monitor.poll(&config).await;
+
+        let status = monitor.status("lobby").unwrap();
+        assert_eq!((status.online, status.max), (0, 50));
+        assert_eq!(status.motd["text"], "Backend");
+        assert!(monitor.status("gone").is_none());
+        assert_eq!(monitor.statuses().len(), 2);
+        assert_eq!(monitor.online_players(), 0);
+    }
+}
@@ -0,0 +1,185 @@
+/// Just enough of NBT to build the tags the protocol wants from us. Reading
+/// only goes as far as [`skip`], since nobody sends us NBT we care about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Builds a compound out of `(name, tag)` pairs, keeping their order.
+    pub fn compound<const N: usize>(entries: [(&str, Tag); N]) -> Tag {
+        Tag::Compound(entries.into_iter().map(|(name, tag)| (name.to_string(), tag)).collect())
+    }
+
+    pub fn string(text: &str) -> Tag {
+        Tag::String(text.to_string())
+    }
+
+    pub fn bool(value: bool) -> Tag {
+        Tag::Byte(value as i8)
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Encodes this as an unnamed root tag, which is how the protocol sends NBT.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![self.id()];
+        write_string(&mut buf, "");
+        self.write_payload(&mut buf);
+
+        buf
+    }
+
+    fn write_payload(&self, buf: &mut Vec<u8>) {
+        match self {
+            Tag::Byte(x) => buf.push(*x as u8),
+            Tag::Int(x) => buf.extend(x.to_be_bytes()),
+            Tag::Long(x) => buf.extend(x.to_be_bytes()),
+            Tag::Float(x) => buf.extend(x.to_be_bytes()),
+            Tag::Double(x) => buf.extend(x.to_be_bytes()),
+            Tag::String(x) => write_string(buf, x),
+            Tag::List(tags) => {
+                // Empty lists are conventionally typed as TAG_End.
+                buf.push(tags.first().map_or(0, Tag::id));
+                buf.extend((tags.len() as i32).to_be_bytes());
+                for tag in tags {
+                    tag.write_payload(buf);
+                }
+            },
+            Tag::Compound(entries) => {
+                for (name, tag) in entries {
+                    buf.push(tag.id());
+                    write_string(buf, name);
+                    tag.write_payload(buf);
+                }
+                buf.push(0);
+            },
+            Tag::LongArray(longs) => {
+                buf.extend((longs.len() as i32).to_be_bytes());
+                for x in longs {
+                    buf.extend(x.to_be_bytes());
+                }
+            },
+        }
+    }
+}
+
+/// Vanilla's limit on how deeply lists and compounds can be nested.
+const MAX_DEPTH: usize = 512;
+
+/// The length of the unnamed root tag `data` starts with, so whatever comes
+/// after it can be read. `None` if it's cut off or nested too deeply.
+pub fn skip(data: &[u8]) -> Option<usize> {
+    let id = *data.first()?;
+    if id == 0 {
+        return Some(1);
+    }
+
+    let name_len = u16::from_be_bytes(data.get(1..3)?.try_into().ok()?) as usize;
+    let start = 3 + name_len;
+
+    Some(start + skip_payload(id, data.get(start..)?, 0)?)
+}
+
+fn skip_payload(id: u8, data: &[u8], depth: usize) -> Option<usize> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let int = |at: usize| data.get(at..at + 4).map(|x| i32::from_be_bytes(x.try_into().unwrap()));
+    let len = match id {
+        1 => 1,
+        2 => 2,
+        3 | 5 => 4,
+        4 | 6 => 8,
+        7 => 4 + usize::try_from(int(0)?).ok()?,
+        8 => 2 + u16::from_be_bytes(data.get(..2)?.try_into().ok()?) as usize,
+        9 => {
+            let item = *data.first()?;
+            let count = usize::try_from(int(1)?).ok()?;
+            let mut len = 5;
+            for _ in 0..count {
+                len += skip_payload(item, data.get(len..)?, depth + 1)?;
+            }
+            len
+        },
+        10 => {
+            let mut len = 0;
+            loop {
+                let item = *data.get(len)?;
+                len += 1;
+                if item == 0 {
+                    break len;
+                }
+
+                len += 2 + u16::from_be_bytes(data.get(len..len + 2)?.try_into().ok()?) as usize;
+                len += skip_payload(item, data.get(len..)?, depth + 1)?;
+            }
+        },
+        11 => 4 + 4 * usize::try_from(int(0)?).ok()?,
+        12 => 4 + 8 * usize::try_from(int(0)?).ok()?,
+        _ => return None,
+    };
+
+    (len <= data.len()).then_some(len)
+}
+
+/// NBT strings are Java's modified UTF-8, which only differs from the real
+/// thing for NUL and characters outside the BMP, neither of which we send.
+fn write_string(buf: &mut Vec<u8>, text: &str) {
+    buf.extend((text.len() as u16).to_be_bytes());
+    buf.extend(text.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Tag, skip};
+
+    #[test]
+    fn nbt_compound() {
+        let tag = Tag::compound([
+            ("a", Tag::Byte(1)),
+            ("list", Tag::List(vec![Tag::Int(2)])),
+            ("empty", Tag::List(vec![])),
+        ]);
+
+        assert_eq!(tag.to_bytes(), [
+            10, 0, 0,
+            1, 0, 1, b'a', 1,
+            9, 0, 4, b'l', b'i', b's', b't', 3, 0, 0, 0, 1, 0, 0, 0, 2,
+            9, 0, 5, b'e', b'm', b'p', b't', b'y', 0, 0, 0, 0, 0,
+            0,
+        ]);
+    }
+
+    #[test]
+    fn skips_tags() {
+        let tag = crate::world::registry_codec().to_bytes();
+        let mut data = tag.clone();
+        data.extend([1, 2, 3]);
+
+        assert_eq!(skip(&data), Some(tag.len()));
+        assert_eq!(skip(&tag[..tag.len() - 1]), None);
+        assert_eq!(skip(&[0]), Some(1));
+    }
+}
@@ -1,12 +1,16 @@
-use hubby_macros::{register_handshaking_packet, generate_handshaking_handler};
-use serde::Deserialize;
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+
+use hubby_macros::{register_handshaking_packet, generate_handshaking_handler, identify_packet};
+use serde::{Deserialize, Serialize};
 use serde_mcje::types::VarInt;
+use uuid::Uuid;
 
-use crate::connection::{Connection, ConnectionState};
+use crate::{config::Forwarding, connection::{Connection, ConnectionState}, server::Server};
 
-use super::HandleError;
+use super::{HandleError, IdentifiedPacket, PROTOCOL_VERSION, VERSION_NAME, login::ForwardedPlayer};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x00)]
 pub struct Handshake {
     pub protocol_version: VarInt,
     pub server_address: String,
@@ -14,17 +18,159 @@ pub struct Handshake {
     pub next_state: VarInt,
 }
 
+/// Vanilla's limit on the hostname in a handshake. The server address can
+/// be longer, with forwarded player info after the hostname.
+pub const MAX_HOST_LENGTH: usize = 255;
+
+/// The hostname a client typed to connect, from its handshake's server
+/// address. Forge tacks `\0FML\0` (or similar) on the end, and BungeeCord
+/// style forwarding everything after a `\0` too, so that's dropped, as is
+/// the trailing dot of a fully qualified name.
+pub fn virtual_host(server_address: &str) -> String {
+    let host = server_address.split('\0').next().unwrap_or_default();
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Reads the client's IP and identity out of a server address in
+/// BungeeCord's forwarding format: `host\0ip\0uuid\0properties`, with the
+/// properties as JSON.
+pub fn legacy_forwarding(server_address: &str) -> Option<(IpAddr, ForwardedPlayer)> {
+    let mut parts = server_address.split('\0').skip(1);
+    let ip = parts.next()?.parse().ok()?;
+    let uuid = Uuid::parse_str(parts.next()?).ok()?;
+    let properties = match parts.next() {
+        Some(json) => serde_json::from_str(json).ok()?,
+        None => vec![],
+    };
+
+    Some((ip, ForwardedPlayer { uuid, properties }))
+}
+
 #[register_handshaking_packet(0x00)]
-async fn handle_handshake(conn: &mut Connection<'_>, packet: Handshake) -> Result<(), HandleError> {
+async fn handle_handshake(server: &Arc<Server>, conn: &mut Connection, packet: Handshake) -> Result<(), HandleError> {
     println!("{:#?}", packet);
 
+    let host = virtual_host(&packet.server_address);
+    if host.chars().count() > MAX_HOST_LENGTH {
+        return Err(HandleError::ProtocolViolation(format!("server address longer than {} characters", MAX_HOST_LENGTH)));
+    }
+    conn.set_virtual_host(host);
+
     conn.switch_state(match packet.next_state.0 {
         1 => ConnectionState::Status,
-        2 => ConnectionState::Play,
-        _ => return Err(HandleError::BadPacket(format!("invalid next_state {}", packet.next_state))),
+        2 => ConnectionState::Login,
+        _ => return Err(HandleError::ProtocolViolation(format!("invalid next_state {}", packet.next_state))),
     });
 
+    let config = server.config();
+    if conn.is_over_capacity() {
+        return Err(HandleError::disconnect(&config.messages.server_full));
+    }
+
+    let forwarding = conn.forwarding(&config);
+    if conn.state() == ConnectionState::Login && forwarding == Forwarding::Legacy {
+        let forwarded = legacy_forwarding(&packet.server_address).filter(|_| config.forwarding.is_trusted(conn.peer().ip()));
+        let Some((ip, player)) = forwarded else {
+            println!("{} tried to log in without going through a proxy", conn.peer());
+            return Err(HandleError::disconnect(&config.messages.not_forwarded));
+        };
+
+        conn.set_peer(SocketAddr::new(ip, conn.peer().port()));
+        *conn.forwarded() = Some(player);
+    }
+
+    // Velocity only says where players really come from later on, during login.
+    let forwarded_later = conn.state() == ConnectionState::Login && forwarding == Forwarding::Velocity;
+    if !forwarded_later && conn.check_throttled() {
+        println!("{} is connecting too fast", conn.peer());
+        return Err(HandleError::disconnect(&config.messages.connection_throttled));
+    }
+
+    if conn.state() == ConnectionState::Login && packet.protocol_version.0 != PROTOCOL_VERSION {
+        let messages = &config.messages;
+        let message = if packet.protocol_version.0 < PROTOCOL_VERSION {
+            &messages.outdated_client
+        } else {
+            &messages.outdated_server
+        };
+
+        return Err(HandleError::disconnect(&message.replace("{version}", VERSION_NAME)));
+    }
+
     Ok(())
 }
 
-generate_handshaking_handler!();
\ No newline at end of file
+generate_handshaking_handler!();
+
+#[cfg(test)]
+mod tests {
+    use crate::{client::{Client, ClientError}, config::{Config, Forwarding}, packets::login::offline_uuid, server::Server};
+
+    use super::{MAX_HOST_LENGTH, legacy_forwarding, virtual_host};
+
+    #[test]
+    fn strips_server_address() {
+        assert_eq!(virtual_host("Play.Example.com"), "play.example.com");
+        assert_eq!(virtual_host("play.example.com.\0FML2\0"), "play.example.com");
+        assert_eq!(virtual_host("play.example.com\x00127.0.0.1\0f84c6a790a4e45e0879bcd49ebd4c4e2\0[]"), "play.example.com");
+        assert_eq!(virtual_host(""), "");
+    }
+
+    #[test]
+    fn reads_legacy_forwarding() {
+        let (ip, player) = legacy_forwarding("mc.example.com\x00203.0.113.7\0f84c6a790a4e45e0879bcd49ebd4c4e2\0[{\"name\":\"textures\",\"value\":\"abc\"}]").unwrap();
+        assert_eq!(ip.to_string(), "203.0.113.7");
+        assert_eq!(player.uuid.simple().to_string(), "f84c6a790a4e45e0879bcd49ebd4c4e2");
+        assert_eq!((player.properties[0].name.as_str(), player.properties[0].signature.as_ref()), ("textures", None));
+
+        assert!(legacy_forwarding("mc.example.com").is_none());
+        assert!(legacy_forwarding("mc.example.com\0FML2\0").is_none());
+    }
+
+    #[tokio::test]
+    async fn legacy_forwarded_login() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        config.forwarding.mode = Forwarding::Legacy;
+        config.limits.connections.burst = 1;
+        let server = Server::builder().config(config).build().unwrap();
+        let address = "localhost\x00203.0.113.7\0f84c6a790a4e45e0879bcd49ebd4c4e2\0[]".to_string();
+
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        client.set_server_address(address.clone());
+        let profile = client.login_offline("Alex").await.unwrap();
+        assert_eq!(format!("{:032x}", profile.uuid), "f84c6a790a4e45e0879bcd49ebd4c4e2");
+
+        // Rate limits apply to the players, not the proxy they all come through.
+        let mut client = Client::in_memory(&server, "127.0.0.1:50001".parse().unwrap());
+        client.set_server_address("localhost\x00203.0.113.8\0e69a79f444e94726a5befca90e38aaf5\0[]".to_string());
+        assert!(client.login_offline("Notch").await.is_ok());
+        let mut client = Client::in_memory(&server, "127.0.0.1:50002".parse().unwrap());
+        assert!(client.status().await.is_ok());
+
+        // Not through a trusted proxy.
+        let mut client = Client::in_memory(&server, "10.0.0.1:50000".parse().unwrap());
+        client.set_server_address(address);
+        assert!(matches!(client.login_offline("Alex").await, Err(ClientError::Disconnected(_))));
+
+        // Not forwarded at all.
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        assert!(matches!(client.login_offline("Alex").await, Err(ClientError::Disconnected(_))));
+        assert!(server.players.get(offline_uuid("Alex")).is_none());
+    }
+
+    #[tokio::test]
+    async fn caps_host_length() {
+        let server = Server::builder().config(Config::default()).build().unwrap();
+
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        client.set_server_address(format!("{}.example.com", "a".repeat(MAX_HOST_LENGTH)));
+        assert!(client.status().await.is_err());
+
+        // Only the hostname counts, not what's forwarded after it.
+        let mut client = Client::in_memory(&server, "127.0.0.1:50001".parse().unwrap());
+        client.set_server_address(format!("localhost\0FML2\0{}", "a".repeat(1000)));
+        assert!(client.status().await.is_ok());
+    }
+}
@@ -0,0 +1,349 @@
+use std::{fmt, net::SocketAddr, sync::Arc};
+
+use hubby_macros::{register_login_packet, generate_login_handler, identify_packet};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, SeqAccess, Visitor}, ser::SerializeStruct};
+use serde_mcje::types::{PrefixedArray, RemainingBytes, VarInt};
+use uuid::{Builder, Uuid};
+
+use crate::{auth, config::{DuplicateLogin, Forwarding, LoginMode}, connection::{Connection, ConnectionState}, forwarding::{VelocityPlayer, VELOCITY_CHANNEL, VELOCITY_VERSION}, proxy::{self, Profile}, player::Player, server::Server};
+
+use super::{HandleError, IdentifiedPacket, text_component};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x00)]
+pub struct LoginDisconnect {
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x01)]
+pub struct EncryptionRequest {
+    pub server_id: String,
+    pub public_key: PrefixedArray<u8>,
+    pub verify_token: PrefixedArray<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x02)]
+pub struct LoginSuccess {
+    pub uuid: u128,
+    pub username: String,
+    pub properties: PrefixedArray<Property>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x03)]
+pub struct SetCompression {
+    pub threshold: VarInt,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x04)]
+pub struct LoginPluginRequest {
+    pub message_id: VarInt,
+    pub channel: String,
+    pub data: RemainingBytes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x00)]
+pub struct LoginStart {
+    pub name: String,
+    pub signature_data: Option<SignatureData>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x02)]
+pub struct LoginPluginResponse {
+    pub message_id: VarInt,
+    /// `None` if the client didn't understand the request.
+    pub data: Option<RemainingBytes>,
+}
+
+/// Chat signing isn't supported, so only the key is used, for checking the
+/// Encryption Response.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SignatureData {
+    pub timestamp: i64,
+    pub public_key: PrefixedArray<u8>,
+    pub signature: PrefixedArray<u8>,
+}
+
+#[derive(Debug)]
+#[identify_packet(0x01)]
+pub struct EncryptionResponse {
+    pub shared_secret: PrefixedArray<u8>,
+    pub verification: Verification,
+}
+
+/// How the client proves it could decrypt our verify token.
+#[derive(Debug)]
+pub enum Verification {
+    VerifyToken(PrefixedArray<u8>),
+    /// Sent instead by clients that gave us their profile key in Login Start.
+    Signature { salt: i64, signature: PrefixedArray<u8> },
+}
+
+struct EncryptionResponseVisitor;
+
+impl<'de> Visitor<'de> for EncryptionResponseVisitor {
+    type Value = EncryptionResponse;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an Encryption Response")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>, {
+        let shared_secret = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let has_verify_token: bool = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+        let verification = if has_verify_token {
+            Verification::VerifyToken(seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?)
+        } else {
+            Verification::Signature {
+                salt: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?,
+                signature: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(3, &self))?,
+            }
+        };
+
+        Ok(EncryptionResponse { shared_secret, verification })
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptionResponse {
+    fn deserialize<D>(deserializer: D) -> Result<EncryptionResponse, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("EncryptionResponse", &["shared_secret", "verification"], EncryptionResponseVisitor)
+    }
+}
+
+impl Serialize for EncryptionResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("EncryptionResponse", 4)?;
+        state.serialize_field("shared_secret", &self.shared_secret)?;
+        match &self.verification {
+            Verification::VerifyToken(token) => {
+                state.serialize_field("has_verify_token", &true)?;
+                state.serialize_field("verify_token", token)?;
+            },
+            Verification::Signature { salt, signature } => {
+                state.serialize_field("has_verify_token", &false)?;
+                state.serialize_field("salt", salt)?;
+                state.serialize_field("signature", signature)?;
+            },
+        }
+
+        state.end()
+    }
+}
+
+/// What's remembered about an online mode login while waiting for the
+/// client's Encryption Response.
+pub struct PendingLogin {
+    pub name: String,
+    pub verify_token: [u8; 4],
+    /// The client's profile key, if it sent one.
+    pub public_key: Option<Vec<u8>>,
+}
+
+/// Who a trusted proxy in front of the hub says a player is.
+pub struct ForwardedPlayer {
+    pub uuid: Uuid,
+    pub properties: Vec<Property>,
+}
+
+/// The UUID vanilla servers give players in offline mode, which is
+/// Java's `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let hash = Md5::digest(format!("OfflinePlayer:{}", name).as_bytes());
+
+    Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
+fn is_valid_username(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[register_login_packet(0x00)]
+async fn handle_login_start(server: &Arc<Server>, conn: &mut Connection, packet: LoginStart) -> Result<(), HandleError> {
+    println!("login started by {} (signed: {})", packet.name, packet.signature_data.is_some());
+
+    if !is_valid_username(&packet.name) {
+        return Err(HandleError::disconnect("Invalid username"));
+    }
+
+    // Everyone comes from the proxy's address until it says where they're
+    // really from, so rate limits wait until then.
+    if conn.forwarding(&server.config()) == Forwarding::Velocity {
+        let message_id = rand::random::<i32>() & i32::MAX;
+        *conn.forwarding_request() = Some(message_id);
+
+        conn.send_packet(LoginPluginRequest {
+            message_id: VarInt(message_id),
+            channel: VELOCITY_CHANNEL.to_string(),
+            data: RemainingBytes(vec![VELOCITY_VERSION]),
+        })?;
+
+        return Ok(());
+    }
+
+    if !server.limits.logins.check(conn.peer().ip()) {
+        return Err(HandleError::disconnect(&server.config().messages.login_throttled));
+    }
+
+    // The proxy has already checked who they are.
+    if let Some(forwarded) = conn.forwarded().take() {
+        return finish_login(server, conn, forwarded.uuid, packet.name, forwarded.properties);
+    }
+
+    let Some(auth) = &server.auth else {
+        let uuid = offline_uuid(&packet.name);
+        return finish_login(server, conn, uuid, packet.name, vec![]);
+    };
+
+    let verify_token = rand::random();
+    *conn.pending_login() = Some(PendingLogin {
+        name: packet.name,
+        verify_token,
+        public_key: packet.signature_data.map(|x| x.public_key.0),
+    });
+
+    conn.send_packet(EncryptionRequest {
+        server_id: String::new(),
+        public_key: PrefixedArray(auth.public_key().to_vec()),
+        verify_token: PrefixedArray(verify_token.to_vec()),
+    })?;
+
+    Ok(())
+}
+
+#[register_login_packet(0x01)]
+async fn handle_encryption_response(server: &Arc<Server>, conn: &mut Connection, packet: EncryptionResponse) -> Result<(), HandleError> {
+    let (Some(auth), Some(pending)) = (&server.auth, conn.pending_login().take()) else {
+        return Err(HandleError::ProtocolViolation("unexpected encryption response".to_string()));
+    };
+
+    let verified = match packet.verification {
+        Verification::VerifyToken(token) => auth.decrypt(&token.0)? == pending.verify_token,
+        Verification::Signature { salt, signature } => pending.public_key.as_ref()
+            .is_some_and(|key| auth::verify_signature(key, &pending.verify_token, salt, &signature.0)),
+    };
+    if !verified {
+        return Err(HandleError::ProtocolViolation("verify token mismatch".to_string()));
+    }
+
+    let shared_secret: [u8; 16] = auth.decrypt(&packet.shared_secret.0)?.try_into()
+        .map_err(|_| HandleError::BadPacket("shared secret isn't 16 bytes".to_string()))?;
+
+    conn.enable_encryption(&shared_secret)?;
+
+    let server_hash = auth::server_hash("", &shared_secret, auth.public_key());
+    let Some(profile) = auth.has_joined(&pending.name, &server_hash).await? else {
+        println!("{} failed to authenticate", pending.name);
+        return Err(HandleError::disconnect(&server.config().messages.failed_to_verify));
+    };
+
+    let uuid = profile.uuid()?;
+    finish_login(server, conn, uuid, profile.name, profile.properties)
+}
+
+#[register_login_packet(0x02)]
+async fn handle_login_plugin_response(server: &Arc<Server>, conn: &mut Connection, packet: LoginPluginResponse) -> Result<(), HandleError> {
+    if conn.forwarding_request().take() != Some(packet.message_id.0) {
+        return Err(HandleError::ProtocolViolation("unexpected login plugin response".to_string()));
+    }
+
+    let config = server.config();
+    let player = match packet.data {
+        Some(data) => VelocityPlayer::read(config.forwarding.secret.as_bytes(), &data.0),
+        None => Err("not answered, so not from Velocity".to_string()),
+    };
+    let player = match player {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{} failed to forward a player; err = {}", conn.peer(), e);
+            return Err(HandleError::disconnect(&config.messages.not_forwarded));
+        },
+    };
+
+    conn.set_peer(SocketAddr::new(player.ip, conn.peer().port()));
+    if conn.check_throttled() {
+        println!("{} is connecting too fast", conn.peer());
+        return Err(HandleError::disconnect(&config.messages.connection_throttled));
+    }
+    if !server.limits.logins.check(player.ip) {
+        return Err(HandleError::disconnect(&config.messages.login_throttled));
+    }
+
+    finish_login(server, conn, player.uuid, player.name, player.properties)
+}
+
+/// Sets up compression if it's on, then sends the client on into the world.
+fn finish_login(server: &Arc<Server>, conn: &mut Connection, uuid: Uuid, username: String, properties: Vec<Property>) -> Result<(), HandleError> {
+    let config = server.config();
+
+    let online = server.players.get(uuid)
+        .or_else(|| (config.login.mode == LoginMode::Offline).then(|| server.players.by_name(&username)).flatten());
+    if let Some(online) = online {
+        println!("{} is already online", online.name);
+        match config.login.duplicates {
+            DuplicateLogin::KickOld => online.handle.disconnect(text_component(&config.messages.logged_in_elsewhere)),
+            DuplicateLogin::RejectNew => return Err(HandleError::disconnect(&config.messages.already_online)),
+        }
+    }
+    let threshold = config.network.compression_threshold;
+    if threshold >= 0 {
+        conn.send_packet(SetCompression { threshold: VarInt(threshold) })?;
+        conn.enable_compression(threshold as usize)?;
+    }
+
+    conn.send_packet(LoginSuccess {
+        uuid: uuid.as_u128(),
+        username: username.clone(),
+        properties: PrefixedArray(properties.clone()),
+    })?;
+
+    conn.switch_state(ConnectionState::Play);
+
+    // In proxy mode the world comes from the lobby backend instead.
+    let (proxy, position) = match config.proxy.enabled {
+        true => {
+            let profile = Profile { uuid, name: username.clone(), properties: properties.clone(), ip: conn.peer().ip() };
+            let lobby = config.lobby(conn.virtual_host()).to_string();
+            let proxy = proxy::start(server.clone(), conn.handle().clone(), profile, lobby);
+            conn.set_proxy(proxy.clone());
+            (Some(proxy), None)
+        },
+        false => {
+            server.world.join(conn, &config)?;
+            (None, Some(config.world.spawn))
+        },
+    };
+
+    let player = Arc::new(Player::new(uuid, username, properties, conn.handle().clone(), proxy));
+    if let Some(position) = position {
+        player.set_position(position);
+    }
+
+    conn.set_player(player.clone());
+    server.add_player(player);
+
+    Ok(())
+}
+
+generate_login_handler!();
\ No newline at end of file
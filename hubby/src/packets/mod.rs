@@ -1,12 +1,99 @@
 pub mod handshaking;
 pub mod status;
+pub mod login;
+pub mod play;
 
+use std::{fmt::{self, Display}, io};
+
+use mc_varint::VarIntError;
+
+pub const PROTOCOL_VERSION: i32 = 759;
+pub const VERSION_NAME: &str = "1.19";
+
+#[derive(Debug)]
 pub enum HandleError {
     SerdeMCJE(serde_mcje::Error),
+    Io(io::Error),
     Unimplemented(i32),
     BadPacket(String),
+    ProtocolViolation(String),
+    Timeout(&'static str),
+    /// Kick the client. Holds the reason as a JSON text component.
+    Disconnect(String),
+}
+
+impl HandleError {
+    pub fn disconnect(text: &str) -> Self {
+        HandleError::Disconnect(text_component(text))
+    }
+
+    /// The text component to show the client before closing the connection,
+    /// or `None` if there's no point in trying to send one.
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            HandleError::Io(_) => None,
+            HandleError::Disconnect(reason) => Some(reason.clone()),
+            HandleError::Timeout(_) => Some(text_component("Timed out")),
+            HandleError::SerdeMCJE(_) | HandleError::BadPacket(_) => Some(text_component("Malformed packet")),
+            HandleError::Unimplemented(id) => Some(text_component(&format!("Unexpected packet 0x{:02X}", id))),
+            HandleError::ProtocolViolation(e) => Some(text_component(&format!("Protocol violation: {}", e))),
+        }
+    }
+}
+
+impl Display for HandleError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HandleError::SerdeMCJE(e) => write!(formatter, "failed to parse packet: {}", e),
+            HandleError::Io(e) => write!(formatter, "I/O error: {}", e),
+            HandleError::Unimplemented(id) => write!(formatter, "packet ID {} unimplemented", id),
+            HandleError::BadPacket(e) => write!(formatter, "invalid packet received: {}", e),
+            HandleError::ProtocolViolation(e) => write!(formatter, "protocol violation: {}", e),
+            HandleError::Timeout(what) => write!(formatter, "timed out: {}", what),
+            HandleError::Disconnect(reason) => write!(formatter, "disconnected: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HandleError::SerdeMCJE(e) => Some(e),
+            HandleError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_mcje::Error> for HandleError {
+    fn from(e: serde_mcje::Error) -> Self {
+        HandleError::SerdeMCJE(e)
+    }
+}
+
+impl From<io::Error> for HandleError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut => HandleError::Timeout("socket I/O"),
+            _ => HandleError::Io(e),
+        }
+    }
+}
+
+impl From<VarIntError> for HandleError {
+    fn from(e: VarIntError) -> Self {
+        match e {
+            VarIntError::Io(e) => HandleError::from(e),
+            VarIntError::Overflow => HandleError::BadPacket("VarInt is too big".to_string()),
+        }
+    }
+}
+
+/// Builds a plain JSON text component, e.g. `{"text":"Hubby"}`.
+pub fn text_component(text: &str) -> String {
+    serde_json::json!({ "text": text }).to_string()
 }
 
 pub trait IdentifiedPacket {
     const ID: i32;
-}
\ No newline at end of file
+}
@@ -0,0 +1,362 @@
+use std::{fmt, sync::Arc};
+
+use hubby_macros::{register_play_packet, generate_play_handler, identify_packet};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, SeqAccess, Visitor}};
+use serde_mcje::types::{PrefixedArray, VarInt};
+
+use crate::{chat, config::Location, connection::Connection, server::Server, tab_list};
+
+use super::{HandleError, IdentifiedPacket, login::{Property, SignatureData}};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x17)]
+pub struct Disconnect {
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x1E)]
+pub struct ClientboundKeepAlive {
+    pub id: i64,
+}
+
+#[derive(Serialize)]
+#[identify_packet(0x1F)]
+pub struct ChunkData {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// NBT, see [`crate::nbt`].
+    pub heightmaps: Vec<u8>,
+    pub data: PrefixedArray<u8>,
+    /// Already encoded. The hub doesn't have any.
+    pub block_entities: PrefixedArray<Vec<u8>>,
+    pub trust_edges: bool,
+    pub sky_light_mask: PrefixedArray<i64>,
+    pub block_light_mask: PrefixedArray<i64>,
+    pub empty_sky_light_mask: PrefixedArray<i64>,
+    pub empty_block_light_mask: PrefixedArray<i64>,
+    pub sky_light: PrefixedArray<PrefixedArray<u8>>,
+    pub block_light: PrefixedArray<PrefixedArray<u8>>,
+}
+
+#[derive(Serialize)]
+#[identify_packet(0x23)]
+pub struct JoinGame {
+    pub entity_id: i32,
+    pub is_hardcore: bool,
+    pub gamemode: u8,
+    pub previous_gamemode: i8,
+    pub dimension_names: PrefixedArray<String>,
+    /// NBT, see [`crate::world::registry_codec`].
+    pub registry_codec: Vec<u8>,
+    pub dimension_type: String,
+    pub dimension_name: String,
+    pub hashed_seed: i64,
+    pub max_players: VarInt,
+    pub view_distance: VarInt,
+    pub simulation_distance: VarInt,
+    pub reduced_debug_info: bool,
+    pub enable_respawn_screen: bool,
+    pub is_debug: bool,
+    pub is_flat: bool,
+    /// The dimension name and position the player last died at.
+    pub death_location: Option<(String, i64)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x34)]
+pub struct PlayerInfo {
+    pub action: PlayerInfoAction,
+}
+
+/// What a Player Info packet does to the tab list, for each of the players
+/// in it. Text is JSON, like everywhere else.
+#[derive(Debug, PartialEq)]
+pub enum PlayerInfoAction {
+    AddPlayers(PrefixedArray<PlayerInfoEntry>),
+    UpdateGameMode(PrefixedArray<(u128, VarInt)>),
+    /// In milliseconds.
+    UpdateLatency(PrefixedArray<(u128, VarInt)>),
+    UpdateDisplayName(PrefixedArray<(u128, Option<String>)>),
+    RemovePlayers(PrefixedArray<u128>),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlayerInfoEntry {
+    pub uuid: u128,
+    pub name: String,
+    pub properties: PrefixedArray<Property>,
+    pub gamemode: VarInt,
+    pub ping: VarInt,
+    /// Shown instead of their name, if there is one.
+    pub display_name: Option<String>,
+    pub signature_data: Option<SignatureData>,
+}
+
+impl Serialize for PlayerInfoAction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            PlayerInfoAction::AddPlayers(x) => (VarInt(0), x).serialize(serializer),
+            PlayerInfoAction::UpdateGameMode(x) => (VarInt(1), x).serialize(serializer),
+            PlayerInfoAction::UpdateLatency(x) => (VarInt(2), x).serialize(serializer),
+            PlayerInfoAction::UpdateDisplayName(x) => (VarInt(3), x).serialize(serializer),
+            PlayerInfoAction::RemovePlayers(x) => (VarInt(4), x).serialize(serializer),
+        }
+    }
+}
+
+struct PlayerInfoActionVisitor;
+
+impl<'de> Visitor<'de> for PlayerInfoActionVisitor {
+    type Value = PlayerInfoAction;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Player Info action")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>, {
+        let action: VarInt = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let missing = || de::Error::invalid_length(1, &self);
+
+        Ok(match action.0 {
+            0 => PlayerInfoAction::AddPlayers(seq.next_element()?.ok_or_else(missing)?),
+            1 => PlayerInfoAction::UpdateGameMode(seq.next_element()?.ok_or_else(missing)?),
+            2 => PlayerInfoAction::UpdateLatency(seq.next_element()?.ok_or_else(missing)?),
+            3 => PlayerInfoAction::UpdateDisplayName(seq.next_element()?.ok_or_else(missing)?),
+            4 => PlayerInfoAction::RemovePlayers(seq.next_element()?.ok_or_else(missing)?),
+            x => return Err(de::Error::invalid_value(de::Unexpected::Signed(x.into()), &self)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for PlayerInfoAction {
+    fn deserialize<D>(deserializer: D) -> Result<PlayerInfoAction, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_tuple(2, PlayerInfoActionVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x36)]
+pub struct SyncPlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Which of the above are relative to the current position.
+    pub flags: u8,
+    pub teleport_id: VarInt,
+    pub dismount_vehicle: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x3B)]
+pub struct Respawn {
+    pub dimension_type: String,
+    pub dimension_name: String,
+    pub hashed_seed: i64,
+    pub gamemode: u8,
+    pub previous_gamemode: i8,
+    pub is_debug: bool,
+    pub is_flat: bool,
+    /// Whether to keep the player's attributes and metadata.
+    pub copy_metadata: bool,
+    pub death_location: Option<(String, i64)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x48)]
+pub struct SetCenterChunk {
+    pub chunk_x: VarInt,
+    pub chunk_z: VarInt,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x4A)]
+pub struct SetDefaultSpawnPosition {
+    pub location: i64,
+    pub angle: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x5F)]
+pub struct SystemChat {
+    /// JSON text.
+    pub content: String,
+    /// Which of the registry codec's chat types it is, see [`crate::chat`].
+    pub chat_type: VarInt,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x60)]
+pub struct SetTabListHeaderAndFooter {
+    /// JSON text. An empty `{"text":""}` takes it away.
+    pub header: String,
+    pub footer: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x00)]
+pub struct ConfirmTeleportation {
+    pub teleport_id: VarInt,
+}
+
+/// A command typed into chat, without the `/`.
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x03)]
+pub struct ChatCommand {
+    pub command: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    /// Each signed argument's name and signature.
+    pub argument_signatures: PrefixedArray<(String, PrefixedArray<u8>)>,
+    pub signed_preview: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x04)]
+pub struct ChatMessage {
+    pub message: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    /// Empty for unsigned messages.
+    pub signature: PrefixedArray<u8>,
+    pub signed_preview: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[identify_packet(0x07)]
+pub struct ClientInformation {
+    /// Like `en_us`, though some clients send `en_US`.
+    pub locale: String,
+    pub view_distance: i8,
+    /// 0 shows all chat, 1 only command output, 2 nothing.
+    pub chat_mode: VarInt,
+    pub chat_colors: bool,
+    /// A bit each for the cape, jacket, sleeves, trouser legs and hat.
+    pub displayed_skin_parts: u8,
+    /// 0 for left, 1 for right.
+    pub main_hand: VarInt,
+    pub enable_text_filtering: bool,
+    pub allow_server_listings: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x11)]
+pub struct ServerboundKeepAlive {
+    pub id: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x13)]
+pub struct SetPlayerPosition {
+    pub x: f64,
+    /// Of the player's feet.
+    pub y: f64,
+    pub z: f64,
+    pub on_ground: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x14)]
+pub struct SetPlayerPositionAndRotation {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x15)]
+pub struct SetPlayerRotation {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+}
+
+#[register_play_packet(0x03)]
+async fn handle_chat_command(server: &Arc<Server>, conn: &mut Connection, packet: ChatCommand) -> Result<(), HandleError> {
+    let Some(player) = conn.player() else { return Ok(()) };
+    chat::validate(&packet.command)?;
+
+    let config = server.config();
+    if config.chat.log {
+        println!("{} issued command /{}", player.name, packet.command);
+    }
+
+    chat::send(player, &config.messages.unknown_command)
+}
+
+#[register_play_packet(0x04)]
+async fn handle_chat_message(server: &Arc<Server>, conn: &mut Connection, packet: ChatMessage) -> Result<(), HandleError> {
+    let Some(player) = conn.player() else { return Ok(()) };
+    chat::validate(&packet.message)?;
+
+    // Signatures are left unchecked, since it's passed on as a system
+    // message, which nothing expects to be signed.
+    chat::player_message(server, player, &packet.message);
+
+    Ok(())
+}
+
+#[register_play_packet(0x07)]
+async fn handle_client_information(_server: &Arc<Server>, conn: &mut Connection, packet: ClientInformation) -> Result<(), HandleError> {
+    if let Some(player) = conn.player() {
+        player.set_settings(packet);
+    }
+
+    Ok(())
+}
+
+/// Applies `update` to the player's position, which starts out wherever
+/// they were spawned.
+fn move_player(conn: &Connection, update: impl FnOnce(&mut Location)) {
+    let Some(player) = conn.player() else { return };
+    if let Some(mut position) = player.position() {
+        update(&mut position);
+        player.set_position(position);
+    }
+}
+
+#[register_play_packet(0x13)]
+async fn handle_set_player_position(_server: &Arc<Server>, conn: &mut Connection, packet: SetPlayerPosition) -> Result<(), HandleError> {
+    move_player(conn, |position| (position.x, position.y, position.z) = (packet.x, packet.y, packet.z));
+
+    Ok(())
+}
+
+#[register_play_packet(0x14)]
+async fn handle_set_player_position_and_rotation(_server: &Arc<Server>, conn: &mut Connection, packet: SetPlayerPositionAndRotation) -> Result<(), HandleError> {
+    move_player(conn, |position| *position = Location { x: packet.x, y: packet.y, z: packet.z, yaw: packet.yaw, pitch: packet.pitch });
+
+    Ok(())
+}
+
+#[register_play_packet(0x15)]
+async fn handle_set_player_rotation(_server: &Arc<Server>, conn: &mut Connection, packet: SetPlayerRotation) -> Result<(), HandleError> {
+    move_player(conn, |position| (position.yaw, position.pitch) = (packet.yaw, packet.pitch));
+
+    Ok(())
+}
+
+#[register_play_packet(0x11)]
+async fn handle_keep_alive(server: &Arc<Server>, conn: &mut Connection, packet: ServerboundKeepAlive) -> Result<(), HandleError> {
+    conn.handle().keep_alive().acknowledge(packet.id)?;
+    if let Some(player) = conn.player() {
+        tab_list::update_latency(server, player);
+    }
+
+    Ok(())
+}
+
+generate_play_handler!();
\ No newline at end of file
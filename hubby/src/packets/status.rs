@@ -1,66 +1,158 @@
+use std::sync::Arc;
+
 use hubby_macros::{register_status_packet, generate_status_handler, identify_packet};
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 
-use crate::connection::Connection;
+use crate::{config::PlayerCount, connection::Connection, server::Server};
 
-use super::{HandleError, IdentifiedPacket};
+use super::{HandleError, IdentifiedPacket, PROTOCOL_VERSION};
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug)]
 #[identify_packet(0x00)]
 pub struct StatusResponse {
     pub status: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Debug)]
 #[identify_packet(0x01)]
 pub struct PingResponse {
     pub payload: i64,
 }
 
-#[derive(Deserialize, Debug)]
+/// Someone in a status response's sample of who's online.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SamplePlayer {
+    pub name: String,
+    pub id: String,
+}
+
+/// How many players vanilla servers show in the server list.
+pub const SAMPLE_SIZE: usize = 12;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x00)]
 pub struct StatusRequest {}
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
+#[identify_packet(0x01)]
 pub struct PingRequest {
     pub payload: i64
 }
 
 #[register_status_packet(0x00)]
-async fn handle_status_request(conn: &mut Connection<'_>, _packet: StatusRequest) -> Result<(), HandleError> {
+async fn handle_status_request(server: &Arc<Server>, conn: &mut Connection, _packet: StatusRequest) -> Result<(), HandleError> {
     println!("status requested");
 
-    let res = StatusResponse { 
-        status: r#"{
-    "version": {
-        "name": "1.19",
-        "protocol": 759
-    },
-    "players": {
-        "max": 100,
-        "online": 0,
-        "sample": []
-    },
-    "description": {
-        "text": "Hubby"
-    },
-    "previewsChat": true
-}"#.to_string(),
+    let config = server.config();
+    let status = &config.status;
+
+    let mut online = server.players.len().min(u32::MAX as usize) as u32;
+    let mut max = status.max_players;
+    let mut sample: Vec<_> = server.players.sample(SAMPLE_SIZE).iter()
+        .map(|player| SamplePlayer { name: player.name.clone(), id: player.uuid.hyphenated().to_string() })
+        .collect();
+    if status.player_count == PlayerCount::Network {
+        for backend in server.monitor.statuses().into_iter().filter_map(|(_, status)| status) {
+            online = online.saturating_add(backend.online);
+            max = max.saturating_add(backend.max);
+            sample.extend(backend.sample);
+        }
+
+        sample.shuffle(&mut rand::thread_rng());
+        sample.truncate(SAMPLE_SIZE);
+    }
+
+    let mut response = serde_json::json!({
+        "version": {
+            "name": status.version_name,
+            "protocol": PROTOCOL_VERSION,
+        },
+        "players": {
+            "max": max,
+            "online": online,
+            "sample": sample,
+        },
+        "description": {
+            "text": config.host(conn.virtual_host()).and_then(|host| host.motd.as_ref()).unwrap_or(&status.motd),
+        },
+        "previewsChat": false,
+    });
+    if let Some(favicon) = server.favicon(conn.virtual_host()) {
+        response["favicon"] = favicon.into();
+    }
+
+    let res = StatusResponse {
+        status: response.to_string(),
     };
 
-    conn.send_packet(res).await;
+    conn.send_packet(res)?;
     
     Ok(())
 }
 
 #[register_status_packet(0x01)]
-async fn handle_ping_request(conn: &mut Connection<'_>, packet: PingRequest) -> Result<(), HandleError> {
+async fn handle_ping_request(_server: &Arc<Server>, conn: &mut Connection, packet: PingRequest) -> Result<(), HandleError> {
     println!("ping requested");
 
     conn.send_packet(PingResponse {
         payload: packet.payload,
-    }).await;
+    })?;
 
     Ok(())
 }
 
-generate_status_handler!();
\ No newline at end of file
+generate_status_handler!();
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use crate::{client::Client, config::{BackendConfig, Config, HostConfig, PlayerCount}, server::Server};
+
+    #[tokio::test]
+    async fn virtual_host_motd() {
+        let mut config = Config::default();
+        config.hosts.push(HostConfig { pattern: "*.example.com".to_string(), motd: Some("Example".to_string()), favicon: None, lobby: None });
+        let server = Server::builder().config(config).build().unwrap();
+
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        client.set_server_address("Play.Example.com.\0FML2\0".to_string());
+        assert_eq!(client.status().await.unwrap()["description"]["text"], "Example");
+
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        client.set_server_address("example.org".to_string());
+        assert_eq!(client.status().await.unwrap()["description"]["text"], "Hubby");
+    }
+
+    #[tokio::test]
+    async fn network_player_count() {
+        let mut config = Config::default();
+        config.status.max_players = 50;
+        let backend = Server::builder().config(config).build().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(backend.clone().accept(listener));
+
+        let mut player = Client::in_memory(&backend, "127.0.0.1:50000".parse().unwrap());
+        player.login_offline("Alex").await.unwrap();
+
+        let mut config = Config::default();
+        config.backends.servers.push(BackendConfig { name: "lobby".to_string(), address });
+        let hub = Server::builder().config(config.clone()).build().unwrap();
+        hub.monitor.poll(&config.backends).await;
+
+        let status = Client::in_memory(&hub, "127.0.0.1:50001".parse().unwrap()).status().await.unwrap();
+        assert_eq!((status["players"]["online"].as_u64(), status["players"]["max"].as_u64()), (Some(0), Some(100)));
+        // Chat previews aren't handled, so clients mustn't send them.
+        assert_eq!(status["previewsChat"], false);
+
+        config.status.player_count = PlayerCount::Network;
+        let hub = Server::builder().config(config.clone()).build().unwrap();
+        hub.monitor.poll(&config.backends).await;
+
+        let status = Client::in_memory(&hub, "127.0.0.1:50001".parse().unwrap()).status().await.unwrap();
+        assert_eq!((status["players"]["online"].as_u64(), status["players"]["max"].as_u64()), (Some(1), Some(150)));
+        assert_eq!(status["players"]["sample"][0]["name"], "Alex");
+    }
+}
@@ -0,0 +1,247 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, RwLock}, time::Duration};
+
+use rand::seq::IteratorRandom;
+use uuid::Uuid;
+
+use crate::{config::Location, connection::ConnectionHandle, packets::{login::Property, play::ClientInformation}, proxy::ProxyHandle};
+
+/// What clients are assumed to speak until they say otherwise.
+pub const DEFAULT_LOCALE: &str = "en_us";
+
+/// Someone who has logged in. Shared between their connection, which keeps
+/// it up to date, and everything else that wants to know about them.
+pub struct Player {
+    pub uuid: Uuid,
+    pub name: String,
+    /// From the session server or a proxy, and usually just the skin.
+    pub properties: Vec<Property>,
+    pub handle: ConnectionHandle,
+    /// Only there in proxy mode.
+    pub proxy: Option<ProxyHandle>,
+    settings: RwLock<Option<ClientInformation>>,
+    position: Mutex<Option<Location>>,
+    display_name: RwLock<Option<String>>,
+}
+
+impl Player {
+    pub fn new(uuid: Uuid, name: String, properties: Vec<Property>, handle: ConnectionHandle, proxy: Option<ProxyHandle>) -> Self {
+        Player {
+            uuid,
+            name,
+            properties,
+            handle,
+            proxy,
+            settings: RwLock::new(None),
+            position: Mutex::new(None),
+            display_name: RwLock::new(None),
+        }
+    }
+
+    /// The signed skin and cape, if they have one.
+    pub fn skin(&self) -> Option<&Property> {
+        self.properties.iter().find(|x| x.name == "textures")
+    }
+
+    /// The settings the client last sent, if it has yet. Proxied players'
+    /// go to their backend instead.
+    pub fn settings(&self) -> Option<ClientInformation> {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: ClientInformation) {
+        *self.settings.write().unwrap() = Some(settings);
+    }
+
+    /// The language the client is in, like `en_us`.
+    pub fn locale(&self) -> String {
+        self.settings.read().unwrap().as_ref()
+            .map_or(DEFAULT_LOCALE.to_string(), |x| x.locale.to_ascii_lowercase())
+    }
+
+    /// The round-trip time of the last keep-alive, if one has come back yet.
+    pub fn ping(&self) -> Option<Duration> {
+        self.handle.keep_alive().latency()
+    }
+
+    /// Where they are in the hub's world. `None` in proxy mode, since only
+    /// the backend knows.
+    pub fn position(&self) -> Option<Location> {
+        *self.position.lock().unwrap()
+    }
+
+    pub fn set_position(&self, position: Location) {
+        *self.position.lock().unwrap() = Some(position);
+    }
+
+    /// JSON text shown instead of their name in the tab list, if any. See
+    /// [`crate::tab_list::set_display_name`].
+    pub fn display_name(&self) -> Option<String> {
+        self.display_name.read().unwrap().clone()
+    }
+
+    pub fn set_display_name(&self, display_name: Option<String>) {
+        *self.display_name.write().unwrap() = display_name;
+    }
+}
+
+type Hook = Box<dyn Fn(&Arc<Player>) + Send + Sync>;
+
+#[derive(Default)]
+struct Entries {
+    by_uuid: HashMap<Uuid, Arc<Player>>,
+    /// Lowercased names, to the UUID of whoever has that name.
+    by_name: HashMap<String, Uuid>,
+}
+
+impl Entries {
+    fn remove(&mut self, uuid: Uuid) -> Option<Arc<Player>> {
+        let player = self.by_uuid.remove(&uuid)?;
+        // Someone else may have taken the name since.
+        let name = player.name.to_ascii_lowercase();
+        if self.by_name.get(&name) == Some(&uuid) {
+            self.by_name.remove(&name);
+        }
+        Some(player)
+    }
+}
+
+/// Everyone who's online, by UUID and by name, ignoring case. Things that
+/// need to know as soon as someone joins or leaves, like the tab list, can
+/// hook in with [`Players::on_join`] and [`Players::on_leave`].
+#[derive(Default)]
+pub struct Players {
+    entries: RwLock<Entries>,
+    on_join: RwLock<Vec<Hook>>,
+    on_leave: RwLock<Vec<Hook>>,
+}
+
+impl Players {
+    /// Calls `hook` with everyone who joins from now on, once they're on
+    /// the list.
+    pub fn on_join(&self, hook: impl Fn(&Arc<Player>) + Send + Sync + 'static) {
+        self.on_join.write().unwrap().push(Box::new(hook));
+    }
+
+    /// Calls `hook` with everyone who leaves from now on, once they're off
+    /// the list.
+    pub fn on_leave(&self, hook: impl Fn(&Arc<Player>) + Send + Sync + 'static) {
+        self.on_leave.write().unwrap().push(Box::new(hook));
+    }
+
+    /// Puts `player` on the list. Whoever was already there with the same
+    /// UUID is taken off it, and returned. Someone else with the same name
+    /// stays on, but is only found by UUID from then on; whether they can
+    /// both be online is up to the login.
+    pub fn add(&self, player: Arc<Player>) -> Option<Arc<Player>> {
+        let replaced = {
+            let mut entries = self.entries.write().unwrap();
+            let replaced = entries.remove(player.uuid);
+
+            entries.by_name.insert(player.name.to_ascii_lowercase(), player.uuid);
+            entries.by_uuid.insert(player.uuid, player.clone());
+            replaced
+        };
+
+        // Hooks are run without the lock held, so they can look players up.
+        if let Some(old) = &replaced {
+            self.on_leave.read().unwrap().iter().for_each(|hook| hook(old));
+        }
+        self.on_join.read().unwrap().iter().for_each(|hook| hook(&player));
+
+        replaced
+    }
+
+    /// Takes `uuid` off the list, as long as the entry is still the one for
+    /// the connection behind `handle`.
+    pub fn remove(&self, uuid: Uuid, handle: &ConnectionHandle) -> Option<Arc<Player>> {
+        let removed = {
+            let mut entries = self.entries.write().unwrap();
+            match entries.by_uuid.get(&uuid) {
+                Some(player) if player.handle.is(handle) => entries.remove(uuid),
+                _ => None,
+            }
+        }?;
+
+        self.on_leave.read().unwrap().iter().for_each(|hook| hook(&removed));
+        Some(removed)
+    }
+
+    pub fn get(&self, uuid: Uuid) -> Option<Arc<Player>> {
+        self.entries.read().unwrap().by_uuid.get(&uuid).cloned()
+    }
+
+    /// The online player called `name`, ignoring case.
+    pub fn by_name(&self, name: &str) -> Option<Arc<Player>> {
+        let entries = self.entries.read().unwrap();
+        entries.by_name.get(&name.to_ascii_lowercase()).and_then(|uuid| entries.by_uuid.get(uuid)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().by_uuid.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Everyone, in no particular order.
+    pub fn all(&self) -> Vec<Arc<Player>> {
+        self.entries.read().unwrap().by_uuid.values().cloned().collect()
+    }
+
+    /// Up to `count` players picked at random.
+    pub fn sample(&self, count: usize) -> Vec<Arc<Player>> {
+        self.entries.read().unwrap().by_uuid.values()
+            .choose_multiple(&mut rand::thread_rng(), count)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use uuid::Uuid;
+
+    use crate::{client::Client, server::Server};
+
+    use super::{Player, Players};
+
+    #[tokio::test]
+    async fn registry() {
+        let server = Server::builder().build().unwrap();
+        let mut client = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        client.login_offline("Steve").await.unwrap();
+        let handle = server.players.by_name("steve").unwrap().handle.clone();
+
+        let players = Players::default();
+        let log = Arc::new(Mutex::new(vec![]));
+        let joins = log.clone();
+        players.on_join(move |player| joins.lock().unwrap().push(format!("+{}", player.name)));
+        let leaves = log.clone();
+        players.on_leave(move |player| leaves.lock().unwrap().push(format!("-{}", player.name)));
+
+        let player = |uuid, name: &str| Arc::new(Player::new(Uuid::from_u128(uuid), name.to_string(), vec![], handle.clone(), None));
+        assert!(players.add(player(1, "Alex")).is_none());
+        assert_eq!(players.by_name("ALEX").unwrap().uuid, Uuid::from_u128(1));
+        assert_eq!(players.get(Uuid::from_u128(1)).unwrap().locale(), "en_us");
+
+        // Another account with the name, as happens in online mode once a
+        // name changes hands, doesn't push the first one out.
+        assert!(players.add(player(2, "alex")).is_none());
+        assert_eq!(players.len(), 2);
+        assert_eq!(players.by_name("Alex").unwrap().uuid, Uuid::from_u128(2));
+        assert!(players.remove(Uuid::from_u128(1), &handle).is_some());
+        assert_eq!(players.by_name("Alex").unwrap().uuid, Uuid::from_u128(2));
+
+        // The same account joining again does.
+        assert_eq!(players.add(player(2, "Alex")).unwrap().name, "alex");
+        assert_eq!(players.len(), 1);
+
+        assert!(players.remove(Uuid::from_u128(2), &handle).is_some());
+        assert!(players.is_empty() && players.by_name("alex").is_none());
+        assert_eq!(*log.lock().unwrap(), ["+Alex", "+alex", "-Alex", "-alex", "+Alex", "-Alex"]);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{fmt::{self, Display}, net::IpAddr, sync::Arc};
+
+use serde::Deserialize;
+use serde_mcje::{from_slice, types::VarInt};
+use tokio::{sync::mpsc, time};
+use uuid::Uuid;
+
+use crate::{client::{Client, ClientError}, config::Forwarding, connection::ConnectionHandle, keep_alive::KEEP_ALIVE_TIMEOUT, nbt, packets::{IdentifiedPacket, login::Property, play::{JoinGame, Respawn}, text_component}, server::Server, varint::*};
+
+/// How many of the player's packets can wait on the backend before the
+/// player has to.
+const QUEUE_CAPACITY: usize = 256;
+
+/// The way to a proxied player's backend. The relay behind it stops once
+/// the player's connection closes.
+#[derive(Clone)]
+pub struct ProxyHandle {
+    /// Packet IDs followed by their data, from the player.
+    packets: mpsc::Sender<Vec<u8>>,
+    /// Names of backends to move the player to.
+    switches: mpsc::UnboundedSender<String>,
+}
+
+impl ProxyHandle {
+    /// Passes a packet ID followed by its data on to the backend, waiting
+    /// while the backend is behind.
+    pub async fn forward(&self, data: Vec<u8>) {
+        // If the relay is gone, so is the player, so there's nobody to tell.
+        let _ = self.packets.send(data).await;
+    }
+
+    /// Moves the player over to `backend`, leaving them where they are if
+    /// that fails.
+    pub fn switch(&self, backend: &str) {
+        let _ = self.switches.send(backend.to_string());
+    }
+}
+
+/// Who a proxied player is, as far as backends are told.
+pub struct Profile {
+    pub uuid: Uuid,
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub ip: IpAddr,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransferError {
+    NotOnline,
+    /// The server isn't in proxy mode.
+    NotProxied,
+    UnknownBackend(String),
+}
+
+impl Display for TransferError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::NotOnline => write!(formatter, "that player isn't online"),
+            TransferError::NotProxied => write!(formatter, "players can only be sent elsewhere in proxy mode"),
+            TransferError::UnknownBackend(name) => write!(formatter, "there's no backend called {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+/// Starts relaying between `player` and the backend called `lobby`, once
+/// they've logged in.
+pub fn start(server: Arc<Server>, player: ConnectionHandle, profile: Profile, lobby: String) -> ProxyHandle {
+    let (packets, packet_queue) = mpsc::channel(QUEUE_CAPACITY);
+    let (switches, switch_queue) = mpsc::unbounded_channel();
+    server.tasks.spawn(relay(server.clone(), player, profile, lobby, packet_queue, switch_queue));
+
+    ProxyHandle { packets, switches }
+}
+
+/// Connects to the backend called `name` and logs in as `profile`.
+async fn connect(server: &Server, profile: &Profile, name: &str) -> Result<Client, String> {
+    let config = server.config();
+    let backend = config.backends.servers.iter().find(|x| x.name == name)
+        .ok_or_else(|| TransferError::UnknownBackend(name.to_string()).to_string())?;
+
+    let mut client = time::timeout(config.backends.timeout, Client::connect(&backend.address)).await
+        .map_err(|_| format!("no answer within {:?}", config.backends.timeout))?
+        .map_err(|e| e.to_string())?;
+
+    if config.proxy.forwarding == Forwarding::Legacy {
+        let host = backend.address.rsplit_once(':').map_or(backend.address.as_str(), |(host, _)| host);
+        let properties = serde_json::to_string(&profile.properties).map_err(|e| e.to_string())?;
+        client.set_server_address(format!("{}\0{}\0{}\0{}", host, profile.ip, profile.uuid.simple(), properties));
+    }
+
+    // Backends send keep-alives every 15 seconds, and might have nothing
+    // else to say in between.
+    client.set_timeout(KEEP_ALIVE_TIMEOUT);
+    client.login_offline(&profile.name).await.map_err(|e| e.to_string())?;
+
+    Ok(client)
+}
+
+/// The parts of Join Game after the registry codec.
+#[derive(Deserialize)]
+struct JoinGameTail {
+    dimension_type: String,
+    dimension_name: String,
+    hashed_seed: i64,
+    _max_players: VarInt,
+    _view_distance: VarInt,
+    _simulation_distance: VarInt,
+    _reduced_debug_info: bool,
+    _enable_respawn_screen: bool,
+    is_debug: bool,
+    is_flat: bool,
+    _death_location: Option<(String, i64)>,
+}
+
+/// Reads which dimension a Join Game puts the player in, and what's needed
+/// to respawn them into any other, skipping over the NBT that keeps it from
+/// just being parsed.
+fn respawn_for(join_game: &[u8]) -> Option<(String, impl Fn(&str) -> Respawn)> {
+    let gamemode = *join_game.get(5)?;
+    let previous_gamemode = *join_game.get(6)? as i8;
+
+    let mut at = 7;
+    let (names, len) = read_varint(join_game.get(at..)?).ok()?;
+    at += len;
+    for _ in 0..names {
+        let (name_len, len) = read_varint(join_game.get(at..)?).ok()?;
+        at += len + usize::try_from(name_len).ok()?;
+    }
+    at += nbt::skip(join_game.get(at..)?)?;
+
+    let tail: JoinGameTail = from_slice(join_game.get(at..)?).ok()?;
+
+    Some((tail.dimension_name.clone(), move |dimension: &str| Respawn {
+        dimension_type: if dimension == tail.dimension_name { tail.dimension_type.clone() } else { dimension.to_string() },
+        dimension_name: dimension.to_string(),
+        hashed_seed: tail.hashed_seed,
+        gamemode,
+        previous_gamemode,
+        is_debug: tail.is_debug,
+        is_flat: tail.is_flat,
+        copy_metadata: false,
+        death_location: None,
+    }))
+}
+
+/// Logs in to the backend called `name`, and gets the player's client to
+/// leave the old backend's world for the new one's.
+async fn switch(server: &Server, profile: &Profile, player: &ConnectionHandle, name: &str) -> Result<Client, String> {
+    let mut backend = connect(server, profile, name).await?;
+
+    let join_game = backend.recv().await.map_err(|e| e.to_string())?;
+    if join_game.id != JoinGame::ID {
+        return Err(format!("expected Join Game, got packet 0x{:02X}", join_game.id));
+    }
+    let (current, respawn) = respawn_for(&join_game.data).ok_or("malformed Join Game")?;
+
+    // Clients take a second Join Game fine, but they only really throw the
+    // old world away on respawning into another dimension. So respawn into
+    // some other dimension first, then into the new one.
+    let mut data = write_varint(JoinGame::ID);
+    data.extend(&join_game.data);
+    let other = if current == "minecraft:overworld" { "minecraft:the_nether" } else { "minecraft:overworld" };
+
+    player.send_raw(data)
+        .and_then(|_| player.send_packet(respawn(other)))
+        .and_then(|_| player.send_packet(respawn(&current)))
+        .map_err(|e| e.to_string())?;
+
+    Ok(backend)
+}
+
+async fn relay(
+    server: Arc<Server>,
+    player: ConnectionHandle,
+    profile: Profile,
+    lobby: String,
+    mut packets: mpsc::Receiver<Vec<u8>>,
+    mut switches: mpsc::UnboundedReceiver<String>,
+) {
+    let unavailable = || text_component(&server.config().messages.backend_unavailable);
+
+    let mut current = lobby;
+    let mut backend = match connect(&server, &profile, &current).await {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("couldn't send {} to {}; err = {}", profile.name, current, e);
+            player.disconnect(unavailable());
+            return;
+        }
+    };
+    println!("{} is on {}", profile.name, current);
+
+    loop {
+        tokio::select! {
+            _ = player.closed() => break,
+            packet = backend.recv() => match packet {
+                Ok(packet) => {
+                    let mut data = write_varint(packet.id);
+                    data.extend(packet.data);
+                    if player.send_raw(data).is_err() {
+                        break;
+                    }
+                },
+                Err(ClientError::Disconnected(reason)) => {
+                    println!("{} was kicked from {}: {}", profile.name, current, reason);
+                    player.disconnect(reason);
+                    break;
+                },
+                Err(e) => {
+                    eprintln!("lost {}'s connection to {}; err = {}", profile.name, current, e);
+                    player.disconnect(unavailable());
+                    break;
+                },
+            },
+            data = packets.recv() => match data {
+                Some(data) => {
+                    // A backend that's stopped reading mustn't keep the
+                    // relay around after the player's gone.
+                    let sent = tokio::select! {
+                        _ = player.closed() => break,
+                        sent = backend.send_raw(data) => sent,
+                    };
+                    if let Err(e) = sent {
+                        eprintln!("lost {}'s connection to {}; err = {}", profile.name, current, e);
+                        player.disconnect(unavailable());
+                        break;
+                    }
+                },
+                None => break,
+            },
+            name = switches.recv() => match name {
+                Some(name) => match switch(&server, &profile, &player, &name).await {
+                    Ok(new) => {
+                        let old = std::mem::replace(&mut backend, new);
+                        if let Err(e) = old.close().await {
+                            eprintln!("failed to leave {} cleanly; err = {}", current, e);
+                        }
+
+                        println!("{} moved from {} to {}", profile.name, current, name);
+                        current = name;
+                    },
+                    Err(e) => eprintln!("couldn't move {} to {}; err = {}", profile.name, name, e),
+                },
+                None => break,
+            },
+        }
+    }
+
+    // Let the backend know the player's gone, rather than having it time out.
+    let _ = time::timeout(server.config().backends.timeout, backend.close()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use serde_mcje::{to_vec, types::PrefixedArray};
+    use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener, sync::oneshot, time};
+
+    use crate::{client::Client, config::{BackendConfig, Config}, packets::{IdentifiedPacket, login::LoginSuccess, play::{JoinGame, Respawn, SyncPlayerPosition}}, server::Server, varint::*};
+
+    use super::TransferError;
+
+    async fn backend(name: &str) -> (Arc<Server>, BackendConfig) {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        let server = Server::builder().config(config).build().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.clone().accept(listener));
+
+        (server, BackendConfig { name: name.to_string(), address })
+    }
+
+    /// Waits for `server` to have `count` players, since leaving a backend
+    /// isn't something the player hears about.
+    async fn wait_for_players(server: &Server, count: usize) {
+        time::timeout(Duration::from_secs(5), async {
+            while server.players.len() != count {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn relays_and_switches() {
+        let (a, a_config) = backend("a").await;
+        let (b, b_config) = backend("b").await;
+
+        let mut config = Config::default();
+        config.proxy.enabled = true;
+        config.proxy.lobby = "a".to_string();
+        config.backends.servers = vec![a_config, b_config];
+        let hub = Server::builder().config(config).build().unwrap();
+
+        let mut player = Client::in_memory(&hub, "127.0.0.1:50000".parse().unwrap());
+        let profile = player.login_offline("Alex").await.unwrap();
+        assert_eq!(player.recv().await.unwrap().id, JoinGame::ID);
+        player.wait_for::<SyncPlayerPosition>().await.unwrap();
+        assert_eq!((hub.players.len(), a.players.len(), b.players.len()), (1, 1, 0));
+
+        let uuid = uuid::Uuid::from_u128(profile.uuid);
+        assert_eq!(hub.send_to_backend(uuid, "c"), Err(TransferError::UnknownBackend("c".to_string())));
+        hub.send_to_backend(uuid, "b").unwrap();
+
+        loop {
+            if player.recv().await.unwrap().id == JoinGame::ID {
+                break;
+            }
+        }
+        assert_eq!(player.expect::<Respawn>().await.unwrap().dimension_name, "minecraft:the_nether");
+        assert_eq!(player.expect::<Respawn>().await.unwrap().dimension_name, "minecraft:overworld");
+        player.wait_for::<SyncPlayerPosition>().await.unwrap();
+
+        wait_for_players(&a, 0).await;
+        wait_for_players(&b, 1).await;
+
+        player.close().await.unwrap();
+        wait_for_players(&b, 0).await;
+        wait_for_players(&hub, 0).await;
+    }
+
+    #[tokio::test]
+    async fn lobby_down() {
+        let mut config = Config::default();
+        config.proxy.enabled = true;
+        config.proxy.lobby = "a".to_string();
+        config.backends.servers = vec![BackendConfig { name: "a".to_string(), address: "127.0.0.1:1".to_string() }];
+        let hub = Server::builder().config(config.clone()).build().unwrap();
+
+        let mut player = Client::in_memory(&hub, "127.0.0.1:50000".parse().unwrap());
+        player.login_offline("Alex").await.unwrap();
+        let reason = player.recv().await.unwrap_err().to_string();
+        assert!(reason.contains(&config.messages.backend_unavailable), "{}", reason);
+    }
+
+    #[tokio::test]
+    async fn backend_stops_reading() {
+        // Logs the player in, then never reads from them again.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (stalled, backend) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // Handshake, then Login Start.
+            for _ in 0..2 {
+                let len = read_varint_async(&mut socket).await.unwrap();
+                socket.read_exact(&mut vec![0; len as usize]).await.unwrap();
+            }
+
+            let mut data = write_varint(LoginSuccess::ID);
+            data.extend(to_vec(&LoginSuccess { uuid: 1, username: "Alex".to_string(), properties: PrefixedArray(Vec::new()) }).unwrap());
+            let mut frame = write_varint(data.len() as i32);
+            frame.extend(data);
+            socket.write_all(&frame).await.unwrap();
+            stalled.send(socket).unwrap();
+        });
+
+        let mut config = Config::default();
+        config.proxy.enabled = true;
+        config.proxy.lobby = "a".to_string();
+        config.backends.servers = vec![BackendConfig { name: "a".to_string(), address }];
+        let hub = Server::builder().config(config).build().unwrap();
+
+        let mut player = Client::in_memory(&hub, "127.0.0.1:50000".parse().unwrap());
+        player.login_offline("Alex").await.unwrap();
+        let backend = backend.await.unwrap();
+
+        // Once the sockets and the queue are full, the hub stops taking
+        // packets from the player, rather than buffering however many
+        // (20 MB here) they send.
+        let mut data = write_varint(0x7F);
+        data.extend([0; 1024]);
+        let mut blocked = false;
+        for _ in 0..20_000 {
+            if time::timeout(Duration::from_millis(500), player.send_raw(data.clone())).await.is_err() {
+                blocked = true;
+                break;
+            }
+        }
+        assert!(blocked);
+
+        // And the player doesn't stay stuck once the backend goes away.
+        drop(backend);
+        wait_for_players(&hub, 0).await;
+    }
+}
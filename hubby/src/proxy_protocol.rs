@@ -0,0 +1,135 @@
+//! The PROXY protocol load balancers use to tell the server behind them who
+//! a connection is really from, in a header sent before anything else. Both
+//! the text (v1) and binary (v2) versions are understood.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+use crate::packets::HandleError;
+
+/// Including the CRLF, as the spec puts it.
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads a PROXY protocol header off the front of `reader`, returning the
+/// address of the client it's for. That's `None` when the balancer speaks
+/// for itself, like on health checks, or doesn't know.
+pub async fn read_header<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<SocketAddr>, HandleError> {
+    match reader.fill_buf().await?.first() {
+        Some(b'P') => read_v1(reader).await,
+        _ => read_v2(reader).await,
+    }
+}
+
+async fn read_v1<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<SocketAddr>, HandleError> {
+    let mut line = Vec::new();
+    (&mut *reader).take(V1_MAX_LENGTH as u64).read_until(b'\n', &mut line).await?;
+    let line = line.strip_suffix(b"\r\n")
+        .and_then(|x| std::str::from_utf8(x).ok())
+        .ok_or_else(|| HandleError::BadPacket("unterminated PROXY header".to_string()))?;
+
+    parse_v1(line).ok_or_else(|| HandleError::BadPacket(format!("invalid PROXY header {:?}", line)))
+}
+
+/// Parses a v1 header, without its CRLF.
+fn parse_v1(line: &str) -> Option<Option<SocketAddr>> {
+    let mut parts = line.split(' ');
+    if parts.next()? != "PROXY" {
+        return None;
+    }
+
+    let family = parts.next()?;
+    if family == "UNKNOWN" {
+        return Some(None);
+    }
+
+    let source: IpAddr = parts.next()?.parse().ok()?;
+    let _destination: IpAddr = parts.next()?.parse().ok()?;
+    let port = parts.next()?.parse().ok()?;
+    let _destination_port: u16 = parts.next()?.parse().ok()?;
+
+    match (family, source) {
+        ("TCP4", IpAddr::V4(_)) | ("TCP6", IpAddr::V6(_)) if parts.next().is_none() => Some(Some(SocketAddr::new(source, port))),
+        _ => None,
+    }
+}
+
+async fn read_v2<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<SocketAddr>, HandleError> {
+    let mut header = [0_u8; 16];
+    reader.read_exact(&mut header).await?;
+    if &header[..12] != V2_SIGNATURE {
+        return Err(HandleError::BadPacket("expected a PROXY header".to_string()));
+    }
+
+    let length = u16::from_be_bytes([header[14], header[15]]);
+    let mut addresses = vec![0_u8; length as usize];
+    reader.read_exact(&mut addresses).await?;
+
+    parse_v2(header[12], header[13], &addresses)
+        .ok_or_else(|| HandleError::BadPacket("invalid PROXY header".to_string()))
+}
+
+/// Parses what follows a v2 header's signature: the version and command,
+/// the address family and transport, then the addresses.
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Option<Option<SocketAddr>> {
+    if version_command >> 4 != 2 {
+        return None;
+    }
+
+    match version_command & 0x0F {
+        // LOCAL: the balancer itself, so it's already who it looks like.
+        0 => return Some(None),
+        1 => {},
+        _ => return None,
+    }
+
+    // Anything left over after the addresses is TLVs, which don't matter here.
+    match family {
+        // TCP over IPv4.
+        0x11 => {
+            let ip: [u8; 4] = addresses.get(..4)?.try_into().ok()?;
+            let port = addresses.get(8..10)?;
+            Some(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), u16::from_be_bytes([port[0], port[1]]))))
+        },
+        // TCP over IPv6.
+        0x21 => {
+            let ip: [u8; 16] = addresses.get(..16)?.try_into().ok()?;
+            let port = addresses.get(32..34)?;
+            Some(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), u16::from_be_bytes([port[0], port[1]]))))
+        },
+        // UNSPEC, UDP or Unix sockets, none of which say anything useful.
+        _ => Some(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_header;
+
+    #[tokio::test]
+    async fn reads_headers() {
+        let mut v1: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 25565\r\n\x10\x00";
+        assert_eq!(read_header(&mut v1).await.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(v1, b"\x10\x00");
+
+        let mut unknown: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_header(&mut unknown).await.unwrap(), None);
+
+        let mut v2 = b"\r\n\r\n\0\r\nQUIT\n\x21\x21\x00\x24".to_vec();
+        v2.extend([0x20, 0x01, 0x0d, 0xb8].iter().chain(&[0; 11]).chain(&[1]));
+        v2.extend([0; 16]);
+        v2.extend([0xC8, 0x22, 0x63, 0xDD, 0x10]);
+        let mut reader = v2.as_slice();
+        assert_eq!(read_header(&mut reader).await.unwrap(), Some("[2001:db8::1]:51234".parse().unwrap()));
+        assert_eq!(reader, [0x10]);
+
+        let mut local: &[u8] = b"\r\n\r\n\0\r\nQUIT\n\x20\x00\x00\x00";
+        assert_eq!(read_header(&mut local).await.unwrap(), None);
+
+        let mut missing: &[u8] = b"\x10\x00\xF7\x05";
+        assert!(read_header(&mut missing).await.is_err());
+        let mut malformed: &[u8] = b"PROXY TCP4 nope 10.0.0.1 1 2\r\n";
+        assert!(read_header(&mut malformed).await.is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashMap, net::IpAddr, sync::{Arc, Mutex}, time::Duration};
+
+use serde::Deserialize;
+use tokio::{sync::{OwnedSemaphorePermit, Semaphore}, time::Instant};
+
+use crate::config::{self, LimitsConfig};
+
+/// How often stale buckets get swept out of a [`RateLimiter`].
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// How many attempts can be made back to back.
+    pub burst: u32,
+    /// How long it takes to earn back a single attempt.
+    #[serde(deserialize_with = "config::seconds")]
+    pub refill: Duration,
+}
+
+/// Everything that decides whether a client gets to connect or log in.
+pub struct Limits {
+    pub connections: RateLimiter,
+    pub logins: RateLimiter,
+    concurrent: Arc<Semaphore>,
+    max_connections: Mutex<usize>,
+}
+
+impl Limits {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Limits {
+            connections: RateLimiter::new(config.connections, config.group_subnets),
+            logins: RateLimiter::new(config.logins, config.group_subnets),
+            concurrent: Arc::new(Semaphore::new(config.max_connections)),
+            max_connections: Mutex::new(config.max_connections),
+        }
+    }
+
+    /// Applies new settings without forgetting who's been rate limited.
+    pub fn reconfigure(&self, config: &LimitsConfig) {
+        self.connections.reconfigure(config.connections, config.group_subnets);
+        self.logins.reconfigure(config.logins, config.group_subnets);
+
+        let mut max_connections = self.max_connections.lock().unwrap();
+        if config.max_connections > *max_connections {
+            self.concurrent.add_permits(config.max_connections - *max_connections);
+        } else if config.max_connections < *max_connections {
+            let excess = *max_connections - config.max_connections;
+            let unused = self.concurrent.forget_permits(excess);
+
+            // The rest are held by open connections, so they're taken out of
+            // circulation as those close instead.
+            if unused < excess {
+                let concurrent = self.concurrent.clone();
+                tokio::spawn(async move {
+                    if let Ok(permits) = concurrent.acquire_many_owned((excess - unused) as u32).await {
+                        permits.forget();
+                    }
+                });
+            }
+        }
+        *max_connections = config.max_connections;
+    }
+
+    /// Takes up one of the concurrent connection slots until the returned
+    /// permit is dropped, or returns `None` if they're all taken.
+    pub fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        self.concurrent.clone().try_acquire_owned().ok()
+    }
+}
+
+struct Bucket {
+    tokens: u32,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Credits the tokens earned since the last update, up to `limit.burst`.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let earned = (now - self.updated).as_nanos() / limit.refill.as_nanos();
+
+        if earned == 0 {
+            return;
+        }
+
+        if earned >= (limit.burst - self.tokens) as u128 {
+            self.tokens = limit.burst;
+            self.updated = now;
+        } else {
+            self.tokens += earned as u32;
+            self.updated += limit.refill * earned as u32;
+        }
+    }
+}
+
+struct LimiterState {
+    limit: RateLimit,
+    group_subnets: bool,
+    buckets: HashMap<IpAddr, Bucket>,
+    pruned: Instant,
+}
+
+/// A token bucket per address (or subnet).
+pub struct RateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit, group_subnets: bool) -> Self {
+        RateLimiter {
+            state: Mutex::new(LimiterState {
+                limit,
+                group_subnets,
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn reconfigure(&self, limit: RateLimit, group_subnets: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        // Buckets are keyed differently depending on grouping, so they can't be kept.
+        if state.group_subnets != group_subnets {
+            state.buckets.clear();
+        }
+        for bucket in state.buckets.values_mut() {
+            bucket.tokens = bucket.tokens.min(limit.burst);
+        }
+
+        state.limit = limit;
+        state.group_subnets = group_subnets;
+    }
+
+    /// Uses up an attempt for `ip`, returning whether it had any left.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let limit = state.limit;
+
+        if limit.refill.is_zero() {
+            return true;
+        }
+
+        // Buckets that have filled back up are no different from ones that
+        // don't exist, so get rid of them before a flood of addresses piles up.
+        if now - state.pruned >= PRUNE_INTERVAL {
+            state.buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst
+            });
+            state.pruned = now;
+        }
+
+        let bucket = state.buckets.entry(key(ip, state.group_subnets)).or_insert(Bucket {
+            tokens: limit.burst,
+            updated: now,
+        });
+        bucket.refill(limit, now);
+
+        if bucket.tokens == 0 {
+            return false;
+        }
+
+        bucket.tokens -= 1;
+        true
+    }
+}
+
+fn key(ip: IpAddr, group_subnets: bool) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) if group_subnets => IpAddr::V4((u32::from(ip) & !0xFF).into()),
+        IpAddr::V6(ip) if group_subnets => IpAddr::V6((u128::from(ip) & !(u64::MAX as u128)).into()),
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use tokio::time::Instant;
+
+    use super::{RateLimit, RateLimiter};
+
+    const LIMIT: RateLimit = RateLimit { burst: 2, refill: Duration::from_secs(1) };
+
+    #[test]
+    fn bucket_refills() {
+        let limiter = RateLimiter::new(LIMIT, false);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(ip, now));
+        assert!(limiter.check_at(ip, now));
+        assert!(!limiter.check_at(ip, now));
+        assert!(!limiter.check_at(ip, now + Duration::from_millis(999)));
+        assert!(limiter.check_at(ip, now + Duration::from_secs(1)));
+        assert!(!limiter.check_at(ip, now + Duration::from_secs(1)));
+        assert!(limiter.check_at(ip, now + Duration::from_secs(60)));
+        assert!(limiter.check_at(ip, now + Duration::from_secs(60)));
+        assert!(!limiter.check_at(ip, now + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn addresses_are_separate() {
+        let limiter = RateLimiter::new(LIMIT, false);
+        let now = Instant::now();
+
+        assert!(limiter.check_at("1.2.3.4".parse().unwrap(), now));
+        assert!(limiter.check_at("1.2.3.4".parse().unwrap(), now));
+        assert!(limiter.check_at("1.2.3.5".parse().unwrap(), now));
+        assert!(limiter.check_at("::ffff:1.2.3.5".parse().unwrap(), now));
+        assert!(!limiter.check_at("1.2.3.5".parse().unwrap(), now));
+    }
+
+    #[test]
+    fn subnets_are_grouped() {
+        let limiter = RateLimiter::new(LIMIT, true);
+        let now = Instant::now();
+
+        assert!(limiter.check_at("1.2.3.4".parse().unwrap(), now));
+        assert!(limiter.check_at("1.2.3.200".parse().unwrap(), now));
+        assert!(!limiter.check_at("1.2.3.5".parse().unwrap(), now));
+        assert!(limiter.check_at("1.2.4.5".parse().unwrap(), now));
+
+        assert!(limiter.check_at("2001:db8::1".parse().unwrap(), now));
+        assert!(limiter.check_at("2001:db8::ffff:1".parse().unwrap(), now));
+        assert!(!limiter.check_at("2001:db8::2".parse().unwrap(), now));
+        assert!(limiter.check_at("2001:db8:0:1::1".parse().unwrap(), now));
+    }
+
+    #[test]
+    fn reconfigure_keeps_buckets() {
+        let limiter = RateLimiter::new(LIMIT, false);
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(ip, now));
+        assert!(limiter.check_at(ip, now));
+        limiter.reconfigure(RateLimit { burst: 1, refill: Duration::from_secs(10) }, false);
+        assert!(!limiter.check_at(ip, now));
+        assert!(!limiter.check_at(ip, now + Duration::from_secs(9)));
+        assert!(limiter.check_at(ip, now + Duration::from_secs(10)));
+
+        limiter.reconfigure(RateLimit { burst: 1, refill: Duration::ZERO }, false);
+        assert!(limiter.check_at(ip, now + Duration::from_secs(10)));
+    }
+}
@@ -0,0 +1,60 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::{sync::mpsc, time};
+
+use crate::server::Server;
+
+/// How long to let changes to the config settle before reloading, since
+/// editors tend to save in more than one step.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Reloads the config, reporting how it went.
+pub fn reload(server: &Server) {
+    match server.reload() {
+        Ok(needs_restart) => {
+            println!("reloaded the config");
+            for key in needs_restart {
+                println!("{} was changed, but only takes effect after a restart", key);
+            }
+        },
+        Err(e) => eprintln!("failed to reload config, keeping the old one; err = {}", e),
+    }
+}
+
+/// Reloads the config whenever its file changes.
+pub async fn watch(server: Arc<Server>) {
+    let Some(path) = server.config_path().cloned() else { return };
+    let file_name = path.file_name().map(|x| x.to_os_string());
+    let (changed, mut changes) = mpsc::unbounded_channel();
+
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+
+        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+            && event.paths.iter().any(|x| x.file_name() == file_name.as_deref()) {
+            let _ = changed.send(());
+        }
+    });
+
+    // Editors often save by replacing the file, which would leave a watch on
+    // the file itself watching nothing, so watch its directory instead.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let _watcher = match watcher.and_then(|mut watcher| watcher.watch(dir, RecursiveMode::NonRecursive).map(|_| watcher)) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("not watching {} for changes; err = {}", path.display(), e);
+            return;
+        }
+    };
+
+    while changes.recv().await.is_some() {
+        time::sleep(DEBOUNCE).await;
+        while changes.try_recv().is_ok() {}
+
+        reload(&server);
+    }
+}
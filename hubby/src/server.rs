@@ -0,0 +1,504 @@
+use std::{collections::HashMap, future::Future, io::{self, ErrorKind}, net::SocketAddr, path::PathBuf, pin::Pin, sync::{Arc, RwLock, atomic::AtomicU64}, time::Duration};
+
+use tokio::{io::{AsyncRead, AsyncWrite}, task::JoinSet, time};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use uuid::Uuid;
+
+use crate::{auth::Authenticator, config::{Config, ConfigError, ListenAddress, ListenerConfig, ListenerOptions, LoginMode}, connection::{Connection, ConnectionHandle, ConnectionState}, console, events::{self, Event, EventBus}, listener, monitor::{self, Monitor}, packets::{HandleError, text_component}, player::{Player, Players}, proxy::TransferError, rate_limit::Limits, reload, tab_list, world::World};
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), HandleError>> + Send>>;
+
+/// A packet handler added with [`ServerBuilder::handler`]. It gets the
+/// packet's data, without the ID.
+pub type Handler = Arc<dyn Fn(Arc<Server>, ConnectionHandle, Vec<u8>) -> HandlerFuture + Send + Sync>;
+
+type Listener = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// How long to wait before accepting again after failing to.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Everything connections share: the config and what was set up from it,
+/// who's online, the world, and the event bus.
+pub struct Server {
+    config_path: Option<PathBuf>,
+    config: RwLock<Arc<Config>>,
+    /// The server icon as a data URI, if one is configured, then each
+    /// virtual host's.
+    favicons: RwLock<(Option<String>, Vec<Option<String>>)>,
+    pub limits: Limits,
+    /// Only there in online mode.
+    pub auth: Option<Authenticator>,
+    pub players: Players,
+    pub world: World,
+    pub events: EventBus,
+    /// The status of the backends in the config.
+    pub monitor: Monitor,
+    pub traffic: Arc<Traffic>,
+    handlers: HashMap<(ConnectionState, i32), Handler>,
+    binds: Vec<SocketAddr>,
+    listeners: RwLock<Vec<Listener>>,
+    console: bool,
+    /// Cancelled once the server starts shutting down.
+    pub(crate) shutdown: CancellationToken,
+    /// Everything shutting down waits on: connections, relays and event
+    /// listeners.
+    pub(crate) tasks: TaskTracker,
+}
+
+/// Packets counted across every connection since the server started.
+#[derive(Default, Debug)]
+pub struct Traffic {
+    /// Read from clients.
+    pub received: AtomicU64,
+    /// Written out to clients.
+    pub sent: AtomicU64,
+}
+
+/// Sets up a [`Server`]. Everything is optional: with nothing set, the
+/// server runs on the default config, listening where it says.
+#[derive(Default)]
+pub struct ServerBuilder {
+    config: Option<Config>,
+    config_path: Option<PathBuf>,
+    binds: Vec<SocketAddr>,
+    handlers: HashMap<(ConnectionState, i32), Handler>,
+    listeners: Vec<Listener>,
+    console: bool,
+}
+
+impl ServerBuilder {
+    /// Reads the config from `path` (writing out the defaults if there's
+    /// nothing there), and reloads it whenever it changes.
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Uses `config` as is, instead of reading it from a file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Listens on `addr` instead of the config's `network.bind`. Can be
+    /// called more than once to listen on several addresses.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.binds.push(addr);
+        self
+    }
+
+    /// Handles packet `id` in `state` with `handler`, instead of the built
+    /// in handler if there is one.
+    pub fn handler<F, Fut>(mut self, state: ConnectionState, id: i32, handler: F) -> Self
+    where
+        F: Fn(Arc<Server>, ConnectionHandle, Vec<u8>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), HandleError>> + Send + 'static,
+    {
+        self.handlers.insert((state, id), Arc::new(move |server, conn, data| Box::pin(handler(server, conn, data))));
+        self
+    }
+
+    /// Calls `listener` with every [`Event`] once the server is running.
+    pub fn listener(mut self, listener: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.listeners.push(Box::new(listener));
+        self
+    }
+
+    /// Whether to take commands (like `reload`) from standard input.
+    pub fn console(mut self, console: bool) -> Self {
+        self.console = console;
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<Server>, ConfigError> {
+        let config = match (self.config, &self.config_path) {
+            (Some(config), _) => {
+                config.validate()?;
+                config
+            },
+            (None, Some(path)) => Config::load(path)?,
+            (None, None) => Config::default(),
+        };
+
+        let auth = match config.login.mode {
+            LoginMode::Online => Some(Authenticator::new(&config.login.session_server)
+                .map_err(|e| ConfigError::Invalid("login.mode", format!("failed to set up online mode: {}", e)))?),
+            LoginMode::Offline => None,
+        };
+
+        let server = Arc::new(Server {
+            config_path: self.config_path,
+            limits: Limits::new(&config.limits),
+            favicons: RwLock::new(config.load_favicons()?),
+            auth,
+            players: Players::default(),
+            world: World::new(),
+            events: EventBus::default(),
+            monitor: Monitor::default(),
+            traffic: Arc::default(),
+            config: RwLock::new(Arc::new(config)),
+            handlers: self.handlers,
+            binds: self.binds,
+            listeners: RwLock::new(self.listeners),
+            console: self.console,
+            shutdown: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+        });
+        tab_list::install(&server);
+
+        Ok(server)
+    }
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Listens for connections until the server is shut down, with
+    /// [`Server::shutdown`], Ctrl-C or SIGTERM, then disconnects everyone.
+    /// Fails right away if any of the listeners can't be set up.
+    pub async fn run(self: Arc<Self>) -> io::Result<()> {
+        let config = self.config();
+        let listen = |address| ListenerConfig { address: ListenAddress::Tcp(address), ipv6_only: false, proxy_protocol: None, forwarding: None };
+        let listeners = match self.binds.is_empty() {
+            true => std::iter::once(listen(config.network.bind)).chain(config.network.listeners.iter().cloned()).collect(),
+            false => self.binds.iter().copied().map(listen).collect::<Vec<_>>(),
+        };
+
+        let mut accepting = JoinSet::new();
+        for config in listeners {
+            let listener = listener::Listener::bind(&config.address, config.ipv6_only)
+                .map_err(|e| io::Error::new(e.kind(), format!("couldn't listen on {}: {}", config.address, e)))?;
+            println!("listening on {}", listener.local_addr()?);
+
+            accepting.spawn(self.clone().serve(listener, config.options()));
+        }
+
+        self.tasks.spawn(events::log(self.events.subscribe()));
+        for listener in self.listeners.write().unwrap().drain(..) {
+            let mut events = self.events.subscribe();
+            self.tasks.spawn(async move {
+                while let Some(event) = events::next(&mut events).await {
+                    listener(&event);
+                    if event == Event::ShuttingDown {
+                        break;
+                    }
+                }
+            });
+        }
+
+        tokio::spawn(monitor::run(self.clone()));
+        tokio::spawn(tab_list::run(self.clone()));
+        if self.config_path.is_some() {
+            tokio::spawn(reload::watch(self.clone()));
+        }
+        if self.console {
+            tokio::spawn(console::run(self.clone()));
+        }
+
+        let result = tokio::select! {
+            result = async {
+                while let Some(result) = accepting.join_next().await {
+                    result.map_err(io::Error::other)?;
+                }
+                Ok(())
+            } => result,
+            _ = signal() => Ok(()),
+            _ = self.shutdown.cancelled() => Ok(()),
+        };
+
+        // Dropping the listeners closes them, and cleans up Unix sockets.
+        accepting.shutdown().await;
+        self.stop().await;
+
+        result
+    }
+
+    /// Starts shutting down: everyone is sent `messages.shutting_down` and
+    /// [`Server::run`] stops listening, then returns once they're gone.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Disconnects everyone and waits, for up to `limits.timeouts.shutdown`,
+    /// for connections, relays and event listeners to wrap up.
+    async fn stop(&self) {
+        println!("shutting down");
+        self.shutdown.cancel();
+        self.events.emit(Event::ShuttingDown);
+
+        self.tasks.close();
+        let timeout = self.config().limits.timeouts.shutdown;
+        if time::timeout(timeout, self.tasks.wait()).await.is_err() {
+            eprintln!("gave up on {} tasks still running after {:?}", self.tasks.len(), timeout);
+        }
+    }
+
+    /// Serves connections from `listener` with no special options, for tests
+    /// that need a server on a real port.
+    #[cfg(test)]
+    pub(crate) async fn accept(self: Arc<Self>, listener: tokio::net::TcpListener) {
+        self.serve(listener::Listener::Tcp(listener), ListenerOptions::default()).await
+    }
+
+    /// Accepts connections from `socket` for as long as the task runs.
+    async fn serve(self: Arc<Self>, socket: listener::Listener, options: ListenerOptions) {
+        loop {
+            let accepted = match &socket {
+                listener::Listener::Tcp(listener) => listener.accept().await.map(|(socket, peer)| {
+                    // IPv4 clients on a dual-stack socket show up as IPv4-mapped IPv6 addresses.
+                    self.spawn_connection(socket, SocketAddr::new(peer.ip().to_canonical(), peer.port()), options);
+                }),
+                #[cfg(unix)]
+                listener::Listener::Unix(listener) => listener.accept().await.map(|(socket, _)| {
+                    self.spawn_connection(socket, listener::UNIX_PEER, options);
+                }),
+            };
+
+            // Errors accepting are usually down to one connection, or to running
+            // out of file descriptors for a moment, so keep going after a pause.
+            if let Err(e) = accepted {
+                eprintln!("failed to accept a connection; err = {}", e);
+                time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+
+    fn spawn_connection<S>(self: &Arc<Self>, socket: S, peer: SocketAddr, options: ListenerOptions)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let permit = self.limits.admit();
+        let mut conn = Connection::new(socket, peer, self.clone()).with_options(options);
+        if permit.is_none() {
+            // It's still accepted, so the client can be told why it's turned
+            // away. The handshake deadline keeps it from lingering.
+            eprintln!("turning away {}; too many connections", peer);
+            conn = conn.over_capacity();
+        }
+
+        self.tasks.spawn(async move {
+            conn.listen().await;
+            drop(permit);
+        });
+    }
+
+    /// The config as it is right now. Hold on to it for the length of
+    /// whatever needs consistent settings, since it can be reloaded any time.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// The icon to show players who connected through `host`.
+    pub fn favicon(&self, host: &str) -> Option<String> {
+        let config = self.config();
+        let favicons = self.favicons.read().unwrap();
+        config.hosts.iter().position(|x| x.matches(host))
+            .and_then(|i| favicons.1.get(i).cloned().flatten())
+            .or_else(|| favicons.0.clone())
+    }
+
+    /// Reads the config file again and applies whatever can be changed while
+    /// running. Returns the settings that were changed but need a restart.
+    /// If the new config is invalid, the old one stays as it is.
+    pub fn reload(&self) -> Result<Vec<&'static str>, ConfigError> {
+        let Some(path) = &self.config_path else {
+            return Err(ConfigError::Io(PathBuf::new(), io::Error::new(ErrorKind::NotFound, "the server wasn't started from a config file")));
+        };
+
+        let mut config = Config::read(path)?;
+        let needs_restart = config.keep_restart_only(&self.config());
+        config.validate()?;
+        let favicons = config.load_favicons()?;
+
+        self.limits.reconfigure(&config.limits);
+        *self.favicons.write().unwrap() = favicons;
+        *self.config.write().unwrap() = Arc::new(config);
+
+        self.events.emit(Event::ConfigReloaded);
+
+        Ok(needs_restart)
+    }
+
+    pub fn config_path(&self) -> Option<&PathBuf> {
+        self.config_path.as_ref()
+    }
+
+    /// The handler added for packet `id` in `state`, if there is one.
+    pub fn handler(&self, state: ConnectionState, id: i32) -> Option<Handler> {
+        self.handlers.get(&(state, id)).cloned()
+    }
+
+    /// Moves the player `uuid` to the backend called `backend`. Only works
+    /// in proxy mode.
+    pub fn send_to_backend(&self, uuid: Uuid, backend: &str) -> Result<(), TransferError> {
+        let config = self.config();
+        if !config.backends.servers.iter().any(|x| x.name == backend) {
+            return Err(TransferError::UnknownBackend(backend.to_string()));
+        }
+
+        let player = self.players.get(uuid).ok_or(TransferError::NotOnline)?;
+        let proxy = player.proxy.as_ref().ok_or(TransferError::NotProxied)?;
+        proxy.switch(backend);
+
+        Ok(())
+    }
+
+    /// Puts `player` on the player list, disconnecting whoever was already
+    /// there with the same UUID.
+    pub fn add_player(&self, player: Arc<Player>) {
+        let event = Event::PlayerJoined { uuid: player.uuid, name: player.name.clone() };
+
+        // Usually they've been kicked already, unless both logged in at once.
+        if let Some(old) = self.players.add(player) {
+            if !old.handle.is_closed() {
+                old.handle.disconnect(text_component(&self.config().messages.logged_in_elsewhere));
+            }
+            self.events.emit(Event::PlayerLeft { uuid: old.uuid, name: old.name.clone() });
+        }
+        self.events.emit(event);
+    }
+
+    /// Takes `uuid` off the player list, as long as the entry is still the
+    /// one for the connection behind `handle`.
+    pub fn remove_player(&self, uuid: Uuid, handle: &ConnectionHandle) {
+        if let Some(player) = self.players.remove(uuid, handle) {
+            self.events.emit(Event::PlayerLeft { uuid, name: player.name.clone() });
+        }
+    }
+}
+
+/// Waits for Ctrl-C or, on Unix, SIGTERM. Never returns if they can't be
+/// listened for.
+async fn signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => terminate.recv().await,
+            Err(e) => {
+                eprintln!("can't listen for SIGTERM; err = {}", e);
+                std::future::pending().await
+            },
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => if let Err(e) = result {
+            eprintln!("can't listen for Ctrl-C; err = {}", e);
+            std::future::pending::<()>().await
+        },
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpStream;
+    #[cfg(unix)]
+    use tokio::net::UnixStream;
+
+    use crate::{client::Client, config::{Config, ListenAddress, ListenerOptions}, connection::ConnectionState, listener::Listener};
+
+    use super::Server;
+
+    #[test]
+    fn builder() {
+        let mut config = Config::default();
+        config.status.motd = "Built".to_string();
+
+        let server = Server::builder()
+            .config(config)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .handler(ConnectionState::Play, 0x0C, |_, _, _| async { Ok(()) })
+            .build()
+            .unwrap();
+
+        assert_eq!(server.config().status.motd, "Built");
+        assert_eq!(server.binds, ["127.0.0.1:0".parse().unwrap()]);
+        assert!(server.handler(ConnectionState::Play, 0x0C).is_some());
+        assert!(server.handler(ConnectionState::Login, 0x0C).is_none());
+        assert!(server.reload().is_err());
+    }
+
+    #[tokio::test]
+    async fn dual_stack_listener() {
+        let server = Server::builder().config(Config::default()).build().unwrap();
+
+        let tcp = Listener::bind(&ListenAddress::Tcp("[::]:0".parse().unwrap()), false).unwrap();
+        let Ok(ListenAddress::Tcp(address)) = tcp.local_addr() else { panic!("not TCP") };
+        tokio::spawn(server.clone().serve(tcp, ListenerOptions::default()));
+
+        let stream = TcpStream::connect(("127.0.0.1", address.port())).await.unwrap();
+        let mut client = Client::new(stream, "127.0.0.1", address.port());
+        assert!(client.status().await.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_listener() {
+        let server = Server::builder().config(Config::default()).build().unwrap();
+
+        let path = std::env::temp_dir().join(format!("hubby-{}.sock", std::process::id()));
+        let address = ListenAddress::Unix(path.clone());
+        // A socket left behind by something that's gone gets replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let unix = Listener::bind(&address, false).unwrap();
+        assert!(Listener::bind(&address, false).is_err());
+        let serving = tokio::spawn(server.clone().serve(unix, ListenerOptions::default()));
+
+        let stream = UnixStream::connect(&path).await.unwrap();
+        let mut client = Client::new(stream, "localhost", 25565);
+        assert!(client.status().await.is_ok());
+
+        serving.abort();
+        assert!(serving.await.is_err());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn turns_away_connections_over_capacity() {
+        let mut config = Config::default();
+        config.limits.max_connections = 1;
+        let server = Server::builder().config(config.clone()).build().unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(server.accept(listener));
+
+        let _first = TcpStream::connect(address).await.unwrap();
+        let stream = TcpStream::connect(address).await.unwrap();
+        let mut client = Client::new(stream, "127.0.0.1", address.port());
+        let reason = client.login_offline("Alex").await.unwrap_err().to_string();
+        assert!(reason.contains(&config.messages.server_full), "{}", reason);
+    }
+
+    #[tokio::test]
+    async fn shutdown_disconnects_everyone() {
+        let server = Server::builder().config(Config::default()).build().unwrap();
+
+        let mut player = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        player.login_offline("Alex").await.unwrap();
+        assert_eq!(server.players.len(), 1);
+
+        server.stop().await;
+        let reason = loop {
+            if let Err(e) = player.recv().await {
+                break e.to_string();
+            }
+        };
+        assert!(reason.contains(&server.config().messages.shutting_down), "{}", reason);
+        assert_eq!(server.players.len(), 0);
+        assert!(server.tasks.is_empty());
+    }
+
+    #[test]
+    fn builder_validates_config() {
+        let mut config = Config::default();
+        config.world.view_distance = 0;
+
+        assert!(Server::builder().config(config).build().is_err());
+    }
+}
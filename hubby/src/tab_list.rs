@@ -0,0 +1,165 @@
+use std::{sync::Arc, time::Duration};
+
+use serde_mcje::types::{PrefixedArray, VarInt};
+use tokio::time;
+
+use crate::{config::Config, packets::{IdentifiedPacket, play::{PlayerInfo, PlayerInfoAction, PlayerInfoEntry, SetTabListHeaderAndFooter}, text_component}, player::Player, server::Server};
+
+/// Shows players on the hub to each other in the tab list as they come and
+/// go. Proxied players are left out, since their backend has its own list.
+pub fn install(server: &Arc<Server>) {
+    let weak = Arc::downgrade(server);
+    server.players.on_join(move |player| {
+        if let Some(server) = weak.upgrade() {
+            joined(&server, player);
+        }
+    });
+
+    let weak = Arc::downgrade(server);
+    server.players.on_leave(move |player| {
+        if let Some(server) = weak.upgrade() {
+            broadcast(&server, PlayerInfo { action: PlayerInfoAction::RemovePlayers(PrefixedArray(vec![player.uuid.as_u128()])) });
+        }
+    });
+}
+
+/// Players on the hub itself, rather than relayed to a backend.
+fn hub_players(server: &Server) -> Vec<Arc<Player>> {
+    server.players.all().into_iter().filter(|x| x.proxy.is_none()).collect()
+}
+
+/// Sends `packet` to everyone on the hub, serializing it just once.
+fn broadcast<T: serde::Serialize + IdentifiedPacket>(server: &Server, packet: T) {
+    let mut data = crate::varint::write_varint(T::ID);
+    match serde_mcje::to_vec(&packet) {
+        Ok(x) => data.extend(x),
+        Err(e) => return eprintln!("failed to serialize packet 0x{:02X}; err = {}", T::ID, e),
+    }
+
+    for player in hub_players(server) {
+        // Whoever's connection is closed is about to leave anyway.
+        let _ = player.handle.send_raw(data.clone());
+    }
+}
+
+fn millis(latency: Option<Duration>) -> i32 {
+    latency.map_or(0, |x| x.as_millis().min(i32::MAX as u128) as i32)
+}
+
+fn entry(player: &Player, config: &Config) -> PlayerInfoEntry {
+    PlayerInfoEntry {
+        uuid: player.uuid.as_u128(),
+        name: player.name.clone(),
+        properties: PrefixedArray(player.properties.clone()),
+        gamemode: VarInt(config.world.gamemode.id() as i32),
+        ping: VarInt(millis(player.ping())),
+        display_name: player.display_name(),
+        signature_data: None,
+    }
+}
+
+fn joined(server: &Server, player: &Arc<Player>) {
+    if player.proxy.is_some() {
+        return;
+    }
+
+    let config = server.config();
+    let others: Vec<_> = hub_players(server).into_iter().filter(|x| x.uuid != player.uuid).collect();
+    for other in &others {
+        let _ = other.handle.send_packet(PlayerInfo { action: PlayerInfoAction::AddPlayers(PrefixedArray(vec![entry(player, &config)])) });
+    }
+
+    let everyone = std::iter::once(player).chain(&others).map(|x| entry(x, &config)).collect();
+    let _ = player.handle.send_packet(PlayerInfo { action: PlayerInfoAction::AddPlayers(PrefixedArray(everyone)) });
+    send_header_and_footer(server, &config, player);
+}
+
+/// Tells everyone on the hub how long `player`'s last keep-alive took.
+pub fn update_latency(server: &Server, player: &Player) {
+    if player.proxy.is_none() {
+        broadcast(server, PlayerInfo { action: PlayerInfoAction::UpdateLatency(PrefixedArray(vec![(player.uuid.as_u128(), VarInt(millis(player.ping())))])) });
+    }
+}
+
+/// Shows `display_name` (JSON text) in place of `player`'s name in the tab
+/// list, or goes back to their name if it's `None`.
+pub fn set_display_name(server: &Server, player: &Player, display_name: Option<String>) {
+    player.set_display_name(display_name.clone());
+    if player.proxy.is_none() {
+        broadcast(server, PlayerInfo { action: PlayerInfoAction::UpdateDisplayName(PrefixedArray(vec![(player.uuid.as_u128(), display_name)])) });
+    }
+}
+
+/// Fills in the placeholders in a configured header or footer for `player`,
+/// and makes it a text component if it isn't one already.
+pub fn render(text: &str, server: &Server, config: &Config, player: &Player) -> String {
+    let text = text
+        .replace("{online}", &server.players.len().to_string())
+        .replace("{max}", &config.status.max_players.to_string())
+        .replace("{player}", &player.name)
+        .replace("{ping}", &millis(player.ping()).to_string());
+
+    match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(component) if component.is_object() || component.is_array() => component.to_string(),
+        _ => text_component(&text),
+    }
+}
+
+fn send_header_and_footer(server: &Server, config: &Config, player: &Player) {
+    let tab_list = &config.tab_list;
+    if tab_list.header.is_empty() && tab_list.footer.is_empty() {
+        return;
+    }
+
+    let _ = player.handle.send_packet(SetTabListHeaderAndFooter {
+        header: render(&tab_list.header, server, config, player),
+        footer: render(&tab_list.footer, server, config, player),
+    });
+}
+
+/// Refreshes everyone's header and footer every `tab_list.refresh`, for as
+/// long as the server runs.
+pub async fn run(server: Arc<Server>) {
+    loop {
+        time::sleep(server.config().tab_list.refresh).await;
+
+        let config = server.config();
+        for player in hub_players(&server) {
+            send_header_and_footer(&server, &config, &player);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_mcje::types::PrefixedArray;
+
+    use crate::{client::Client, config::Config, packets::play::{PlayerInfo, PlayerInfoAction, SetTabListHeaderAndFooter}, server::Server};
+
+    #[tokio::test]
+    async fn players_see_each_other() {
+        let mut config = Config::default();
+        config.world.view_distance = 2;
+        config.tab_list.header = "Welcome, {player}!".to_string();
+        config.tab_list.footer = r#"{"text":"{online}/{max}","color":"gold"}"#.to_string();
+        let server = Server::builder().config(config).build().unwrap();
+
+        let mut alex = Client::in_memory(&server, "127.0.0.1:50000".parse().unwrap());
+        alex.login_offline("Alex").await.unwrap();
+        let PlayerInfoAction::AddPlayers(added) = alex.wait_for::<PlayerInfo>().await.unwrap().action else { panic!("not added") };
+        assert_eq!(added.0.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["Alex"]);
+        let header_and_footer = alex.wait_for::<SetTabListHeaderAndFooter>().await.unwrap();
+        assert_eq!(header_and_footer.header, r#"{"text":"Welcome, Alex!"}"#);
+        assert_eq!(header_and_footer.footer, r#"{"color":"gold","text":"1/100"}"#);
+
+        let mut steve = Client::in_memory(&server, "127.0.0.1:50001".parse().unwrap());
+        let profile = steve.login_offline("Steve").await.unwrap();
+        let PlayerInfoAction::AddPlayers(added) = steve.wait_for::<PlayerInfo>().await.unwrap().action else { panic!("not added") };
+        assert_eq!(added.0.len(), 2);
+        let PlayerInfoAction::AddPlayers(added) = alex.wait_for::<PlayerInfo>().await.unwrap().action else { panic!("not added") };
+        assert_eq!(added.0[0].name, "Steve");
+
+        steve.close().await.unwrap();
+        assert_eq!(alex.wait_for::<PlayerInfo>().await.unwrap().action, PlayerInfoAction::RemovePlayers(PrefixedArray(vec![profile.uuid])));
+    }
+}
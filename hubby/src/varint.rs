@@ -1,9 +1,9 @@
 
 
-use tokio::{net::TcpStream, io::AsyncReadExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
 pub use mc_varint::*;
 
-pub async fn read_varint_tcp(socket: &mut TcpStream) -> Result<i32, VarIntError>  {
+pub async fn read_varint_async<R: AsyncRead + Unpin>(socket: &mut R) -> Result<i32, VarIntError>  {
     let mut value: i32 = 0;
     let mut pos: u8 = 0;
     let mut current_byte: u8;
@@ -27,7 +27,7 @@ pub async fn read_varint_tcp(socket: &mut TcpStream) -> Result<i32, VarIntError>
     Ok(value)
 }
 
-pub async fn read_varlong_tcp(socket: &mut TcpStream) -> Result<i64, VarIntError>  {
+pub async fn read_varlong_async<R: AsyncRead + Unpin>(socket: &mut R) -> Result<i64, VarIntError>  {
     let mut value: i64 = 0;
     let mut pos: u8 = 0;
     let mut current_byte: u8;
@@ -0,0 +1,318 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use serde::Deserialize;
+use serde_mcje::types::{PrefixedArray, VarInt};
+use mc_varint::write_varint;
+
+use crate::{config::Config, connection::Connection, nbt::Tag, packets::{HandleError, play::{ChunkData, JoinGame, SetCenterChunk, SetDefaultSpawnPosition, SyncPlayerPosition}}};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    #[serde(rename = "minecraft:overworld")]
+    Overworld,
+    #[serde(rename = "minecraft:the_nether")]
+    Nether,
+    #[serde(rename = "minecraft:the_end")]
+    End,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::End];
+
+    /// Both the dimension's name and the name of its dimension type, which
+    /// are the same for all the vanilla ones.
+    pub fn name(self) -> &'static str {
+        match self {
+            Dimension::Overworld => "minecraft:overworld",
+            Dimension::Nether => "minecraft:the_nether",
+            Dimension::End => "minecraft:the_end",
+        }
+    }
+
+    pub fn min_y(self) -> i32 {
+        match self {
+            Dimension::Overworld => -64,
+            Dimension::Nether | Dimension::End => 0,
+        }
+    }
+
+    pub fn height(self) -> i32 {
+        match self {
+            Dimension::Overworld => 384,
+            Dimension::Nether | Dimension::End => 256,
+        }
+    }
+
+    /// The dimension type as vanilla defines it.
+    fn element(self) -> Tag {
+        let (infiniburn, fixed_time, has_skylight, has_ceiling, ambient_light, coordinate_scale) = match self {
+            Dimension::Overworld => ("#minecraft:infiniburn_overworld", None, true, false, 0.0, 1.0),
+            Dimension::Nether => ("#minecraft:infiniburn_nether", Some(18000), false, true, 0.1, 8.0),
+            Dimension::End => ("#minecraft:infiniburn_end", Some(6000), false, false, 0.0, 1.0),
+        };
+
+        let mut element = vec![
+            ("piglin_safe", Tag::bool(self == Dimension::Nether)),
+            ("natural", Tag::bool(self == Dimension::Overworld)),
+            ("ambient_light", Tag::Float(ambient_light)),
+            ("monster_spawn_block_light_limit", Tag::Int(if self == Dimension::Nether { 15 } else { 0 })),
+            ("monster_spawn_light_level", Tag::Int(if self == Dimension::Nether { 11 } else { 0 })),
+            ("infiniburn", Tag::string(infiniburn)),
+            ("respawn_anchor_works", Tag::bool(self == Dimension::Nether)),
+            ("has_skylight", Tag::bool(has_skylight)),
+            ("bed_works", Tag::bool(self == Dimension::Overworld)),
+            ("effects", Tag::string(self.name())),
+            ("has_raids", Tag::bool(self != Dimension::Nether)),
+            ("min_y", Tag::Int(self.min_y())),
+            ("height", Tag::Int(self.height())),
+            ("logical_height", Tag::Int(if self == Dimension::Nether { 128 } else { self.height() })),
+            ("coordinate_scale", Tag::Double(coordinate_scale)),
+            ("ultrawarm", Tag::bool(self == Dimension::Nether)),
+            ("has_ceiling", Tag::bool(has_ceiling)),
+        ];
+        if let Some(time) = fixed_time {
+            element.push(("fixed_time", Tag::Long(time)));
+        }
+
+        Tag::Compound(element.into_iter().map(|(name, tag)| (name.to_string(), tag)).collect())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl GameMode {
+    pub fn id(self) -> u8 {
+        match self {
+            GameMode::Survival => 0,
+            GameMode::Creative => 1,
+            GameMode::Adventure => 2,
+            GameMode::Spectator => 3,
+        }
+    }
+}
+
+fn registry(kind: &str, entries: Vec<(&str, Tag)>) -> Tag {
+    let entries = entries.into_iter().enumerate()
+        .map(|(id, (name, element))| Tag::compound([
+            ("name", Tag::string(name)),
+            ("id", Tag::Int(id as i32)),
+            ("element", element),
+        ]))
+        .collect();
+
+    Tag::compound([
+        ("type", Tag::string(kind)),
+        ("value", Tag::List(entries)),
+    ])
+}
+
+fn chat_decoration(translation_key: &str) -> Tag {
+    Tag::compound([
+        ("translation_key", Tag::string(translation_key)),
+        ("parameters", Tag::List(vec![Tag::string("sender"), Tag::string("content")])),
+        ("style", Tag::compound([])),
+    ])
+}
+
+/// The registries the client needs to know about before it can join: the
+/// dimension types, a single biome (which every chunk we send is filled
+/// with) and the chat types.
+pub fn registry_codec() -> Tag {
+    let plains = Tag::compound([
+        ("precipitation", Tag::string("rain")),
+        ("temperature", Tag::Float(0.8)),
+        ("downfall", Tag::Float(0.4)),
+        ("effects", Tag::compound([
+            ("sky_color", Tag::Int(7907327)),
+            ("water_fog_color", Tag::Int(329011)),
+            ("fog_color", Tag::Int(12638463)),
+            ("water_color", Tag::Int(4159204)),
+            ("mood_sound", Tag::compound([
+                ("tick_delay", Tag::Int(6000)),
+                ("offset", Tag::Double(2.0)),
+                ("sound", Tag::string("minecraft:ambient.cave")),
+                ("block_search_extent", Tag::Int(8)),
+            ])),
+        ])),
+    ]);
+
+    // System Chat refers to these by their position in the list, which
+    // crate::chat has constants for.
+    let chat_types = vec![
+        ("minecraft:chat", Tag::compound([
+            ("chat", Tag::compound([("decoration", chat_decoration("chat.type.text"))])),
+            ("narration", Tag::compound([
+                ("decoration", chat_decoration("chat.type.text.narrate")),
+                ("priority", Tag::string("chat")),
+            ])),
+        ])),
+        ("minecraft:system", Tag::compound([
+            ("chat", Tag::compound([])),
+            ("narration", Tag::compound([("priority", Tag::string("system"))])),
+        ])),
+        ("minecraft:game_info", Tag::compound([
+            ("overlay", Tag::compound([])),
+        ])),
+    ];
+
+    Tag::compound([
+        ("minecraft:dimension_type", registry(
+            "minecraft:dimension_type",
+            Dimension::ALL.iter().map(|x| (x.name(), x.element())).collect(),
+        )),
+        ("minecraft:worldgen/biome", registry("minecraft:worldgen/biome", vec![("minecraft:plains", plains)])),
+        ("minecraft:chat_type", registry("minecraft:chat_type", chat_types)),
+    ])
+}
+
+/// Packs block coordinates into the protocol's 64-bit Position.
+pub fn encode_position(x: i32, y: i32, z: i32) -> i64 {
+    ((x as i64 & 0x3FFFFFF) << 38) | ((z as i64 & 0x3FFFFFF) << 12) | (y as i64 & 0xFFF)
+}
+
+/// The data of a chunk column of nothing but air, which is all the hub's
+/// world is made of.
+fn empty_chunk_sections(dimension: Dimension) -> Vec<u8> {
+    let mut section = 0_i16.to_be_bytes().to_vec();
+    // Block states, then biomes, each a single-valued palette (0 bits per
+    // entry) of the first ID, with no data array.
+    for _ in 0..2 {
+        section.push(0);
+        section.extend(write_varint(0));
+        section.extend(write_varint(0));
+    }
+
+    section.repeat((dimension.height() / 16) as usize)
+}
+
+/// An all-zero MOTION_BLOCKING heightmap. Heights take 9 bits in every
+/// vanilla dimension, so 7 fit in a long and 256 of them take 37.
+fn empty_heightmaps() -> Tag {
+    Tag::compound([("MOTION_BLOCKING", Tag::LongArray(vec![0; 37]))])
+}
+
+/// The hub's world. It's empty, so there's not much to it beyond handing
+/// out entity IDs; what it looks like is up to the `[world]` config.
+pub struct World {
+    /// [`registry_codec`], already encoded, since it never changes.
+    registry_codec: Vec<u8>,
+    next_entity_id: AtomicI32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            registry_codec: registry_codec().to_bytes(),
+            next_entity_id: AtomicI32::new(0),
+        }
+    }
+
+    pub fn next_entity_id(&self) -> i32 {
+        self.next_entity_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Takes a freshly logged in player into the world: Join Game, the empty
+    /// chunks around spawn, and finally their position, which gets them past
+    /// the loading screen.
+    pub fn join(&self, conn: &mut Connection, config: &Config) -> Result<(), HandleError> {
+        let world = &config.world;
+        let spawn = world.spawn;
+
+        conn.send_packet(JoinGame {
+            entity_id: self.next_entity_id(),
+            is_hardcore: false,
+            gamemode: world.gamemode.id(),
+            previous_gamemode: -1,
+            dimension_names: PrefixedArray(Dimension::ALL.iter().map(|x| x.name().to_string()).collect()),
+            registry_codec: self.registry_codec.clone(),
+            dimension_type: world.dimension.name().to_string(),
+            dimension_name: world.dimension.name().to_string(),
+            hashed_seed: 0,
+            max_players: VarInt(config.status.max_players.min(i32::MAX as u32) as i32),
+            view_distance: VarInt(world.view_distance as i32),
+            simulation_distance: VarInt(world.view_distance as i32),
+            reduced_debug_info: false,
+            enable_respawn_screen: true,
+            is_debug: false,
+            is_flat: true,
+            death_location: None,
+        })?;
+
+        let (block_x, block_y, block_z) = (spawn.x.floor() as i32, spawn.y.floor() as i32, spawn.z.floor() as i32);
+        conn.send_packet(SetDefaultSpawnPosition {
+            location: encode_position(block_x, block_y, block_z),
+            angle: spawn.yaw,
+        })?;
+
+        let (center_x, center_z) = (block_x >> 4, block_z >> 4);
+        conn.send_packet(SetCenterChunk { chunk_x: VarInt(center_x), chunk_z: VarInt(center_z) })?;
+
+        let sections = empty_chunk_sections(world.dimension);
+        let heightmaps = empty_heightmaps().to_bytes();
+        let radius = world.view_distance as i32;
+        for chunk_x in center_x - radius..=center_x + radius {
+            for chunk_z in center_z - radius..=center_z + radius {
+                conn.send_packet(ChunkData {
+                    chunk_x,
+                    chunk_z,
+                    heightmaps: heightmaps.clone(),
+                    data: PrefixedArray(sections.clone()),
+                    block_entities: PrefixedArray(vec![]),
+                    trust_edges: true,
+                    sky_light_mask: PrefixedArray(vec![]),
+                    block_light_mask: PrefixedArray(vec![]),
+                    empty_sky_light_mask: PrefixedArray(vec![]),
+                    empty_block_light_mask: PrefixedArray(vec![]),
+                    sky_light: PrefixedArray(vec![]),
+                    block_light: PrefixedArray(vec![]),
+                })?;
+            }
+        }
+
+        conn.send_packet(SyncPlayerPosition {
+            x: spawn.x,
+            y: spawn.y,
+            z: spawn.z,
+            yaw: spawn.yaw,
+            pitch: spawn.pitch,
+            flags: 0,
+            teleport_id: VarInt(0),
+            dismount_vehicle: false,
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dimension, empty_chunk_sections, encode_position};
+
+    #[test]
+    fn positions() {
+        assert_eq!(encode_position(0, 0, 0), 0);
+        // The example from wiki.vg: x, z and y in 26, 26 and 12 bits.
+        let expected = (0b01000110000001110110001100 << 38) | (0b10110000010101101101001000 << 12) | 0b001100111111;
+        assert_eq!(encode_position(18357644, 831, -20882616), expected);
+        assert_eq!(encode_position(-1, -1, -1), -1);
+    }
+
+    #[test]
+    fn empty_chunks() {
+        assert_eq!(empty_chunk_sections(Dimension::Overworld).len(), 24 * 8);
+        assert_eq!(empty_chunk_sections(Dimension::End).len(), 16 * 8);
+    }
+}
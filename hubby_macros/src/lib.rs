@@ -75,9 +75,9 @@ fn generate_generic_handler(reg: RwLockReadGuard<PacketRegistry>, _input: TokenS
     let funcs: Vec<Ident> = reg.iter().map(|x| syn::Ident::new(&x.name, Span::call_site())).collect();
 
     TokenStream::from(quote!(
-        pub async fn handle(conn: &mut Connection<'_>, id: i32, data: &[u8]) -> Result<(), HandleError> {
+        pub async fn handle(server: &std::sync::Arc<Server>, conn: &mut Connection, id: i32, data: &[u8]) -> Result<(), HandleError> {
             match id {
-                #(#ids => #funcs(conn, serde_mcje::from_slice(data).map_err(HandleError::SerdeMCJE)?).await,)*
+                #(#ids => #funcs(server, conn, serde_mcje::from_slice(data).map_err(HandleError::SerdeMCJE)?).await,)*
                 _ => Err(HandleError::Unimplemented(id))
             }
         }
@@ -1,4 +1,4 @@
-use std::fmt::{Display, self};
+use std::{fmt::{Display, self}, io::ErrorKind};
 
 #[derive(Debug)]
 pub enum VarIntError {
@@ -26,7 +26,7 @@ pub fn read_varint(vec: &[u8]) -> Result<(i32, usize)> {
     let mut current_byte: u8;
 
     loop {
-        current_byte = vec[i];
+        current_byte = *vec.get(i).ok_or_else(|| VarIntError::Io(ErrorKind::UnexpectedEof.into()))?;
         i += 1;
 
         value |= (current_byte as i32 & 0x7F) << pos;
@@ -71,7 +71,7 @@ pub fn read_varlong(vec: &[u8]) -> Result<(i64, usize)> {
     let mut current_byte: u8;
 
     loop {
-        current_byte = vec[i];
+        current_byte = *vec.get(i).ok_or_else(|| VarIntError::Io(ErrorKind::UnexpectedEof.into()))?;
         i += 1;
 
         value |= (current_byte as i64 & 0x7F) << pos;
@@ -128,6 +128,13 @@ mod tests {
         assert_eq!(crate::read_varint(&[0x80, 0x80, 0x80, 0x80, 0x08]).unwrap(), (-2147483648, 5));
     }
 
+    #[test]
+    fn read_varint_truncated() {
+        assert!(crate::read_varint(&[]).is_err());
+        assert!(crate::read_varint(&[0x80]).is_err());
+        assert!(crate::read_varlong(&[0xff, 0xff]).is_err());
+    }
+
     #[test]
     fn write_varint_works() {
         let mut vec: Vec<u8> = vec![];
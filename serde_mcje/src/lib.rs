@@ -11,7 +11,7 @@ pub use ser::{to_vec, Serializer};
 mod tests {
     use serde::{Deserialize, Serialize};
 
-    use crate::{types::{VarInt, VarLong}, to_vec, from_vec};
+    use crate::{types::{VarInt, VarLong, PrefixedArray, RemainingBytes}, to_vec, from_vec};
 
     #[derive(Serialize, Deserialize, PartialEq, Debug)]
     struct TestChild {
@@ -44,6 +44,21 @@ mod tests {
         child: TestChild
     }
 
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestOptionals {
+        uuid: u128,
+        present: Option<String>,
+        absent: Option<i32>,
+        array: PrefixedArray<TestEntry>,
+        bytes: PrefixedArray<u8>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct TestEntry {
+        name: String,
+        value: Option<VarInt>,
+    }
+
     #[test]
     fn serde_roundtrip() {
         let x = TestStruct {
@@ -79,4 +94,47 @@ mod tests {
 
         assert_eq!(x, y);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn serde_optionals_and_arrays() {
+        let x = TestOptionals {
+            uuid: 0x0123456789abcdef_fedcba9876543210,
+            present: Some("here".to_string()),
+            absent: None,
+            array: PrefixedArray(vec![
+                TestEntry { name: "a".to_string(), value: Some(VarInt(300)) },
+                TestEntry { name: "b".to_string(), value: None },
+            ]),
+            bytes: PrefixedArray(vec![1, 2, 3]),
+        };
+
+        let vec = to_vec(&x).unwrap();
+
+        assert_eq!(&vec[..16], &0x0123456789abcdef_fedcba9876543210_u128.to_be_bytes());
+        assert_eq!(&vec[16..22], &[0x01, 0x04, b'h', b'e', b'r', b'e']);
+        assert_eq!(vec[22], 0x00);
+        assert_eq!(&vec[vec.len() - 4..], &[0x03, 1, 2, 3]);
+
+        let y: TestOptionals = from_vec(&vec).unwrap();
+
+        assert_eq!(x, y);
+    }
+
+    #[test]
+    fn remaining_bytes() {
+        let x = (VarInt(1), Some(RemainingBytes(vec![4, 5, 6])));
+        let vec = to_vec(&x).unwrap();
+        assert_eq!(vec, [0x01, 0x01, 4, 5, 6]);
+
+        let y: (VarInt, Option<RemainingBytes>) = from_vec(&vec).unwrap();
+        assert_eq!(x, y);
+
+        let (_, rest): (VarInt, RemainingBytes) = from_vec(&[0x01]).unwrap();
+        assert!(rest.0.is_empty());
+    }
+
+    #[test]
+    fn string_longer_than_input() {
+        assert!(from_vec::<String>(&[0x05, b'a', b'b']).is_err());
+    }
+}
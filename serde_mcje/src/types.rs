@@ -1,9 +1,9 @@
-use std::fmt;
+use std::{fmt, marker::PhantomData};
 
-use serde::{Serialize, Deserialize, de::{self, Visitor}, Deserializer};
+use serde::{Serialize, Deserialize, de::{self, Visitor}, ser::SerializeSeq, Deserializer};
 use mc_varint::*;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct VarInt(pub i32);
 
 impl From<VarInt> for i32 {
@@ -84,7 +84,7 @@ impl<'de> Deserialize<'de> for VarInt {
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub struct VarLong(pub i64);
 
 impl From<VarLong> for i64 {
@@ -163,4 +163,112 @@ impl<'de> Deserialize<'de> for VarLong {
     {
         deserializer.deserialize_newtype_struct("VarLong", VarLongVisitor)
     }
-}
\ No newline at end of file
+}
+
+/// An array preceded by its length as a VarInt, which is how the protocol
+/// sends nearly every list (plain sequences are written back to back).
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct PrefixedArray<T>(pub Vec<T>);
+
+impl<T> From<PrefixedArray<T>> for Vec<T> {
+    fn from(x: PrefixedArray<T>) -> Vec<T> {
+        x.0
+    }
+}
+
+impl<T> From<Vec<T>> for PrefixedArray<T> {
+    fn from(x: Vec<T>) -> Self {
+        PrefixedArray(x)
+    }
+}
+
+impl<T: Serialize> Serialize for PrefixedArray<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer {
+        let mut seq = serializer.serialize_seq(Some(self.0.len() + 1))?;
+        seq.serialize_element(&VarInt(self.0.len() as i32))?;
+        for x in &self.0 {
+            seq.serialize_element(x)?;
+        }
+        seq.end()
+    }
+}
+
+struct PrefixedArrayVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for PrefixedArrayVisitor<T> {
+    type Value = PrefixedArray<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a VarInt-prefixed array")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: de::SeqAccess<'de>, {
+        let len: VarInt = seq.next_element()?
+            .ok_or_else(|| serde::de::Error::custom("Missing array length"))?;
+
+        if len.0 < 0 {
+            return Err(serde::de::Error::custom("Negative array length"));
+        }
+
+        // The length comes straight off the wire, so don't trust it for the
+        // allocation. Reading past the end of the input will fail soon enough.
+        let mut vec = Vec::with_capacity((len.0 as usize).min(1024));
+        for _ in 0..len.0 {
+            vec.push(seq.next_element()?
+                .ok_or_else(|| serde::de::Error::custom("Array is shorter than its length"))?);
+        }
+
+        Ok(PrefixedArray(vec))
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for PrefixedArray<T> {
+    fn deserialize<D>(deserializer: D) -> Result<PrefixedArray<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(PrefixedArrayVisitor(PhantomData))
+    }
+}
+
+/// Everything up to the end of the packet, with no length in front. Only
+/// makes sense as the last field.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct RemainingBytes(pub Vec<u8>);
+
+impl Serialize for RemainingBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+struct RemainingBytesVisitor;
+
+impl<'de> Visitor<'de> for RemainingBytesVisitor {
+    type Value = RemainingBytes;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the rest of the input")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+        where
+            E: de::Error, {
+        Ok(RemainingBytes(v.to_vec()))
+    }
+}
+
+impl<'de> Deserialize<'de> for RemainingBytes {
+    fn deserialize<D>(deserializer: D) -> Result<RemainingBytes, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(RemainingBytesVisitor)
+    }
+}